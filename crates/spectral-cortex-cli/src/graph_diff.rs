@@ -0,0 +1,180 @@
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+use serde::Serialize;
+use spectral_cortex::SpectralMemoryGraph;
+
+/// A note matched by content in both graphs whose cluster label differs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub(crate) struct LabelChange {
+    pub(crate) note_id_a: u32,
+    pub(crate) note_id_b: u32,
+    pub(crate) label_a: usize,
+    pub(crate) label_b: usize,
+}
+
+/// Differences between two graphs, matching notes by a hash of their raw content.
+///
+/// Note ids are local to each graph, so long-range links are compared as unordered
+/// pairs of content hashes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct GraphDiff {
+    /// Note ids (in A) whose content does not appear in B.
+    pub(crate) only_in_a: Vec<u32>,
+    /// Note ids (in B) whose content does not appear in A.
+    pub(crate) only_in_b: Vec<u32>,
+    pub(crate) label_changes: Vec<LabelChange>,
+    /// Long-range links present in B but not in A.
+    pub(crate) links_added: usize,
+    /// Long-range links present in A but not in B.
+    pub(crate) links_removed: usize,
+}
+
+fn content_hash(text: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Map content hash -> lowest note id with that content.
+fn notes_by_hash(smg: &SpectralMemoryGraph) -> HashMap<u64, u32> {
+    let mut by_hash: HashMap<u64, u32> = HashMap::new();
+    for (nid, note) in &smg.notes {
+        let entry = by_hash.entry(content_hash(&note.raw_content)).or_insert(*nid);
+        *entry = (*entry).min(*nid);
+    }
+    by_hash
+}
+
+/// Map note id -> cluster label using the sorted note-id order labels are indexed by.
+fn labels_by_note(smg: &SpectralMemoryGraph) -> HashMap<u32, usize> {
+    let Some(labels) = &smg.cluster_labels else {
+        return HashMap::new();
+    };
+    let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
+    note_ids.sort_unstable();
+    note_ids.into_iter().zip(labels.iter().copied()).collect()
+}
+
+fn link_set(smg: &SpectralMemoryGraph) -> BTreeSet<(u64, u64)> {
+    smg.long_range_links
+        .iter()
+        .flatten()
+        .filter_map(|(a, b, _)| {
+            let ha = content_hash(&smg.notes.get(a)?.raw_content);
+            let hb = content_hash(&smg.notes.get(b)?.raw_content);
+            Some((ha.min(hb), ha.max(hb)))
+        })
+        .collect()
+}
+
+/// Characters of note context shown per note by `diff`.
+const SNIPPET_CHARS: usize = 100;
+
+/// The note's context, truncated to `SNIPPET_CHARS` characters (not bytes) with `...`.
+pub(crate) fn note_snippet(smg: &SpectralMemoryGraph, nid: &u32) -> String {
+    let context = smg.notes.get(nid).map(|n| n.context()).unwrap_or_default();
+    match context.char_indices().nth(SNIPPET_CHARS) {
+        Some((end, _)) => format!("{}...", &context[..end]),
+        None => context,
+    }
+}
+
+/// Compare graph `a` against graph `b`.
+pub(crate) fn diff_graphs(a: &SpectralMemoryGraph, b: &SpectralMemoryGraph) -> GraphDiff {
+    let hashes_a = notes_by_hash(a);
+    let hashes_b = notes_by_hash(b);
+    let labels_a = labels_by_note(a);
+    let labels_b = labels_by_note(b);
+
+    let mut diff = GraphDiff::default();
+    for (hash, nid_a) in &hashes_a {
+        match hashes_b.get(hash) {
+            None => diff.only_in_a.push(*nid_a),
+            Some(nid_b) => {
+                if let (Some(la), Some(lb)) = (labels_a.get(nid_a), labels_b.get(nid_b)) {
+                    if la != lb {
+                        diff.label_changes.push(LabelChange {
+                            note_id_a: *nid_a,
+                            note_id_b: *nid_b,
+                            label_a: *la,
+                            label_b: *lb,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    diff.only_in_b = hashes_b
+        .iter()
+        .filter(|(hash, _)| !hashes_a.contains_key(hash))
+        .map(|(_, nid)| *nid)
+        .collect();
+    diff.only_in_a.sort_unstable();
+    diff.only_in_b.sort_unstable();
+    diff.label_changes.sort_by_key(|c| c.note_id_a);
+
+    let links_a = link_set(a);
+    let links_b = link_set(b);
+    diff.links_added = links_b.difference(&links_a).count();
+    diff.links_removed = links_a.difference(&links_b).count();
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff_graphs, note_snippet};
+    use spectral_cortex::{SMGNote, SpectralMemoryGraph};
+
+    fn graph(contents: &[&str], labels: Vec<usize>, links: Vec<(u32, u32, f32)>) -> SpectralMemoryGraph {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for (i, content) in contents.iter().enumerate() {
+            smg.notes.insert(i as u32, SMGNote::new(i as u32, *content, vec![1.0, 0.0]));
+        }
+        smg.next_id = contents.len() as u32;
+        smg.cluster_labels = Some(labels.into());
+        smg.long_range_links = Some(links);
+        smg
+    }
+
+    #[test]
+    fn test_diff_reports_added_removed_and_relabelled_notes() {
+        let a = graph(
+            &["alpha", "beta", "gamma", "delta"],
+            vec![0, 0, 1, 1],
+            vec![(0, 2, 0.9), (1, 3, 0.8)],
+        );
+        // "beta" removed, "epsilon" added, "delta" moved to cluster 0; ids shift in B.
+        let b = graph(
+            &["alpha", "gamma", "delta", "epsilon"],
+            vec![0, 1, 0, 1],
+            vec![(0, 1, 0.9), (2, 3, 0.7)],
+        );
+
+        let diff = diff_graphs(&a, &b);
+        assert_eq!(diff.only_in_a, vec![1]);
+        assert_eq!(diff.only_in_b, vec![3]);
+        assert_eq!(diff.label_changes.len(), 1);
+        assert_eq!(
+            (diff.label_changes[0].note_id_a, diff.label_changes[0].note_id_b),
+            (3, 2)
+        );
+        // alpha-gamma is kept, beta-delta removed, delta-epsilon added.
+        assert_eq!((diff.links_added, diff.links_removed), (1, 1));
+
+        assert_eq!(diff_graphs(&a, &a), super::GraphDiff::default());
+    }
+
+    #[test]
+    fn test_snippet_truncates_non_ascii_on_char_boundaries() {
+        // Byte 100 falls inside a multibyte character for both contents.
+        let long = format!("a{}", "é".repeat(120));
+        let cjk = "修复解析器崩溃".repeat(20);
+        let smg = graph(&[&long, &cjk, "短い"], vec![0, 0, 0], vec![]);
+
+        let snippet = note_snippet(&smg, &0);
+        assert_eq!(snippet, format!("a{}...", "é".repeat(99)));
+        assert_eq!(note_snippet(&smg, &1).chars().count(), 103);
+        assert_eq!(note_snippet(&smg, &2), "短い");
+    }
+}
@@ -21,7 +21,7 @@
 //!    `ingest_turn` and `build_spectral_structure`) to perform work. Persistence
 //!    (save/load) will be added in later phases.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Instant;
@@ -36,17 +36,26 @@ use serde_json::json;
 use spectral_cortex::embed;
 
 mod git_commit_split;
+mod graph_diff;
 mod mcp_server;
+mod query_json;
+mod score_stats;
 pub mod ast;
 
-use crate::git_commit_split::{split_commit_message, CommitSplitConfig, CommitSplitStats};
+use crate::git_commit_split::{
+    split_commit_message, CommitSegment, CommitSplitConfig, CommitSplitStats,
+};
 use crate::mcp_server::run_mcp_server;
+use crate::query_json::{
+    build_query_results, query_result_for_turn, turn_note_index, QueryResultEntry,
+};
 
 /// Local library crate export (hyphen -> underscore).
 use spectral_cortex::{
-    load_smg_json, save_smg_json,
-    temporal::{TemporalConfig, TemporalMode},
-    ConversationTurn, SpectralMemoryGraph,
+    export_labels_csv, load_smg_json, save_smg_json_clusters,
+    save_smg_json_with_options,
+    temporal::{TemporalConfig, TemporalMode, TimestampSource},
+    ConversationTurn, SmgSaveOptions, SpectralMemoryGraph,
 };
 
 /// CLI entrypoint.
@@ -84,6 +93,24 @@ enum Commands {
 
     /// Retrieve chronological change history for a single symbol.
     History(HistoryArgs),
+
+    /// List clusters and, optionally, how their topics relate to each other.
+    Clusters(ClustersArgs),
+
+    /// Export derived data from a persisted SMG.
+    Export(ExportArgs),
+
+    /// Compare two SMG files (notes matched by content).
+    Diff(DiffArgs),
+
+    /// Re-split and re-ingest a single commit into an existing SMG, then rebuild.
+    Reingest(ReingestArgs),
+
+    /// Print the embedding of an arbitrary text (for debugging the embedder).
+    Embed(EmbedArgs),
+
+    /// Report score percentiles over a batch of queries (for tuning `--min-score`).
+    Stats(StatsArgs),
 }
 
 /// Arguments for the `ingest` subcommand.
@@ -109,6 +136,12 @@ struct IngestArgs {
     #[arg(long)]
     max_commits: Option<usize>,
 
+    /// Maximum number of notes in the resulting graph, counted after commit splitting.
+    /// With `--append`, notes already in the loaded SMG count toward the cap.
+    /// Combined with `--max-commits`, whichever limit is reached first applies.
+    #[arg(long)]
+    max_notes: Option<usize>,
+
     /// Number of parallel embedding workers (default: 4).
     #[arg(long, default_value = "4")]
     workers: usize,
@@ -129,6 +162,10 @@ struct IngestArgs {
     #[arg(long = "git-filter-case-insensitive")]
     git_filter_case_insensitive: bool,
 
+    /// Handling of merge commits (more than one parent): include|skip|subject-only.
+    #[arg(long = "merge-commit-policy", default_value = "include")]
+    merge_commit_policy: String,
+
     /// Only ingest commits that are not already present in the target SMG (matched by commit_id).
     /// Recommended for post-commit hooks with `--append --out <smg.json>`.
     #[arg(long)]
@@ -157,6 +194,18 @@ struct IngestArgs {
     /// Maximum cluster count allowed by eigengap selection.
     #[arg(long = "max-clusters")]
     max_clusters: Option<usize>,
+
+    /// Maximum number of long-range links kept during the build (strongest first).
+    #[arg(long = "max-links")]
+    max_links: Option<usize>,
+
+    /// Merge turns whose content exactly matches an existing note instead of adding a duplicate note.
+    #[arg(long = "dedup-exact")]
+    dedup_exact: bool,
+
+    /// Also persist the sparsified similarity matrix so it is restored on load (larger files).
+    #[arg(long = "persist-similarity")]
+    persist_similarity: bool,
 }
 
 /// Arguments for the `update` subcommand.
@@ -174,6 +223,13 @@ struct UpdateArgs {
     #[arg(long)]
     max_commits: Option<usize>,
 
+    /// Maximum number of notes in the updated graph, counted after commit splitting.
+    /// Notes already in the loaded SMG count toward the cap, so only the remaining budget
+    /// is ingested (nothing if the SMG is already at or above it). Combined with
+    /// `--max-commits`, whichever limit is reached first applies.
+    #[arg(long)]
+    max_notes: Option<usize>,
+
     /// Number of parallel embedding workers (default: 4).
     #[arg(long, default_value = "4")]
     workers: usize,
@@ -194,6 +250,10 @@ struct UpdateArgs {
     #[arg(long = "git-filter-case-insensitive")]
     git_filter_case_insensitive: bool,
 
+    /// Handling of merge commits (more than one parent): include|skip|subject-only.
+    #[arg(long = "merge-commit-policy", default_value = "include")]
+    merge_commit_policy: String,
+
     /// Commit message split mode: off|auto|strict.
     #[arg(long = "git-commit-split-mode", default_value = "auto")]
     git_commit_split_mode: String,
@@ -217,6 +277,18 @@ struct UpdateArgs {
     /// Maximum cluster count allowed by eigengap selection.
     #[arg(long = "max-clusters")]
     max_clusters: Option<usize>,
+
+    /// Maximum number of long-range links kept during the build (strongest first).
+    #[arg(long = "max-links")]
+    max_links: Option<usize>,
+
+    /// Merge turns whose content exactly matches an existing note instead of adding a duplicate note.
+    #[arg(long = "dedup-exact")]
+    dedup_exact: bool,
+
+    /// Also persist the sparsified similarity matrix so it is restored on load (larger files).
+    #[arg(long = "persist-similarity")]
+    persist_similarity: bool,
 }
 
 /// Arguments for the `query` subcommand (skeleton).
@@ -260,20 +332,36 @@ struct QueryArgs {
     #[arg(long, default_value_t = 14.0)]
     temporal_half_life_days: f32,
 
-    /// Optional override of "now" for reproducible queries / testing (RFC3339 string).
+    /// Optional override of "now" for reproducible queries / testing (RFC3339 string or epoch seconds).
     #[arg(long)]
     temporal_now: Option<String>,
 
-    /// Output results as JSON to stdout.
+    /// Timestamp driving temporal decay: commit|ingest|blend. Default: commit
+    #[arg(long, default_value = "commit")]
+    temporal_source: String,
+
+    /// Weight of the ingest-time score when `--temporal-source blend` (0..1). Default: 0.5
+    #[arg(long, default_value_t = 0.5)]
+    temporal_ingest_weight: f32,
+
+    /// Output results as JSON to stdout (shorthand for `--format json`).
     #[arg(long)]
     json: bool,
 
-    /// Optional start time for filtering notes (RFC3339 string).
+    /// Output format: human|json|ids. `ids` prints only the ranked ids, one per line.
+    #[arg(long, default_value = "human")]
+    format: String,
+
+    /// Id kind printed by `--format ids`: turn|note.
+    #[arg(long = "id-kind", default_value = "turn")]
+    id_kind: String,
+
+    /// Optional start time for filtering notes (RFC3339 string or epoch seconds).
     /// Only notes with timestamps >= this time will be considered.
     #[arg(long)]
     time_start: Option<String>,
 
-    /// Optional end time for filtering notes (RFC3339 string).
+    /// Optional end time for filtering notes (RFC3339 string or epoch seconds).
     /// Only notes with timestamps <= this time will be considered.
     #[arg(long)]
     time_end: Option<String>,
@@ -323,6 +411,10 @@ struct NoteArgs {
     #[arg(long)]
     links_k: Option<usize>,
 
+    /// Also show the cheapest long-range link path from this note to the given note id.
+    #[arg(long = "path-to", value_name = "ID")]
+    path_to: Option<u32>,
+
     /// Output as JSON.
     #[arg(long)]
     json: bool,
@@ -334,6 +426,10 @@ struct McpArgs {
     /// Path to the SMG JSON file to preload and serve.
     #[arg(short = 's', long = "smg", value_name = "PATH")]
     smg: PathBuf,
+
+    /// Cache results of up to N distinct queries for the session (disabled by default).
+    #[arg(long = "query-cache", value_name = "N")]
+    query_cache: Option<usize>,
 }
 
 /// Arguments for the `hotspots` subcommand.
@@ -364,6 +460,182 @@ struct HistoryArgs {
     pub limit: Option<usize>,
 }
 
+/// Arguments for the `clusters` subcommand.
+#[derive(Args, Debug)]
+struct ClustersArgs {
+    /// Path to the SMG JSON file to load.
+    #[arg(short = 's', long = "smg", value_name = "PATH")]
+    smg: PathBuf,
+
+    /// Include the pairwise cosine similarity between cluster centroids.
+    #[arg(long)]
+    relationships: bool,
+
+    /// Number of salient (TF-IDF) terms to show per cluster; 0 disables.
+    #[arg(long = "terms", value_name = "N", default_value_t = 5)]
+    terms: usize,
+
+    /// Output as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+/// Arguments for the `export` subcommand.
+#[derive(Args, Debug)]
+struct ExportArgs {
+    /// Path to the SMG JSON file to load.
+    #[arg(short = 's', long = "smg", value_name = "PATH")]
+    smg: PathBuf,
+
+    /// Path to write the export to.
+    #[arg(short = 'o', long = "out", value_name = "PATH")]
+    out: PathBuf,
+
+    /// Export format: labels-csv (`note_id,cluster_label` rows) or smg (filtered SMG JSON).
+    #[arg(long, default_value = "labels-csv")]
+    format: String,
+
+    /// Cluster labels to keep for `--format smg` (comma-separated).
+    #[arg(long, value_delimiter = ',', value_name = "LABELS")]
+    clusters: Vec<usize>,
+}
+
+/// Arguments for the `diff` subcommand.
+#[derive(Args, Debug)]
+struct DiffArgs {
+    /// Path to the baseline SMG JSON file.
+    #[arg(long = "a", value_name = "PATH")]
+    a: PathBuf,
+
+    /// Path to the SMG JSON file compared against the baseline.
+    #[arg(long = "b", value_name = "PATH")]
+    b: PathBuf,
+
+    /// Output as JSON.
+    #[arg(long)]
+    json: bool,
+}
+
+/// Arguments for the `reingest` subcommand.
+#[derive(Args, Debug)]
+struct ReingestArgs {
+    /// Path to the git repository (defaults to current directory).
+    #[arg(short, long, value_name = "PATH", default_value = ".")]
+    repo: PathBuf,
+
+    /// Path to SMG JSON file to update in place.
+    #[arg(long, short = 'o', value_name = "PATH")]
+    out: PathBuf,
+
+    /// Commit to re-ingest (full or abbreviated SHA, or any revision).
+    #[arg(long, value_name = "REV")]
+    commit: String,
+
+    /// Number of parallel embedding workers (default: 4).
+    #[arg(long, default_value = "4")]
+    workers: usize,
+
+    /// Cache size per worker (default: 100).
+    #[arg(long, default_value = "100")]
+    cache_size: usize,
+
+    /// Drop commit message lines that match this regex. Repeatable.
+    #[arg(long = "git-filter-drop", value_name = "REGEX")]
+    git_filter_drop: Vec<String>,
+
+    /// Built-in line filter preset. Supported: git-noise
+    #[arg(long = "git-filter-preset", value_name = "NAME")]
+    git_filter_preset: Option<String>,
+
+    /// Apply case-insensitive matching for git line filters.
+    #[arg(long = "git-filter-case-insensitive")]
+    git_filter_case_insensitive: bool,
+
+    /// Handling of merge commits (more than one parent): include|skip|subject-only.
+    #[arg(long = "merge-commit-policy", default_value = "include")]
+    merge_commit_policy: String,
+
+    /// Commit message split mode: off|auto|strict.
+    #[arg(long = "git-commit-split-mode", default_value = "auto")]
+    git_commit_split_mode: String,
+
+    /// Maximum number of segments emitted per commit.
+    #[arg(long = "git-commit-split-max-segments", default_value_t = 6)]
+    git_commit_split_max_segments: usize,
+
+    /// Minimum parser confidence for emitting split segments in auto mode (0.0..1.0).
+    #[arg(long = "git-commit-split-min-confidence", default_value_t = 0.75)]
+    git_commit_split_min_confidence: f32,
+
+    /// Persist the sparsified similarity matrix. Implied when the loaded SMG already has one.
+    #[arg(long = "persist-similarity")]
+    persist_similarity: bool,
+}
+
+/// Arguments for the `embed` subcommand.
+#[derive(Args, Debug)]
+struct EmbedArgs {
+    /// Text to embed.
+    #[arg(long, conflicts_with = "file", required_unless_present = "file")]
+    text: Option<String>,
+
+    /// Read the text to embed from this file.
+    #[arg(long, value_name = "PATH")]
+    file: Option<PathBuf>,
+
+    /// Output format: json|npy. `npy` requires `--out`.
+    #[arg(long, default_value = "json")]
+    format: String,
+
+    /// Write the `.npy` vector to this path.
+    #[arg(long, short = 'o', value_name = "PATH")]
+    out: Option<PathBuf>,
+
+    /// Number of parallel embedding workers.
+    #[arg(long, default_value = "1")]
+    workers: usize,
+
+    /// Cache size per worker.
+    #[arg(long, default_value = "0")]
+    cache_size: usize,
+}
+
+/// Arguments for the `stats` subcommand.
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Path to a saved SMG JSON file to load.
+    #[arg(short = 's', long)]
+    smg: PathBuf,
+
+    /// File with one query per line; blank lines and lines starting with `#` are skipped.
+    #[arg(long, value_name = "PATH")]
+    queries_file: PathBuf,
+
+    /// Number of results retrieved per query.
+    #[arg(long, default_value_t = 5)]
+    top_k: usize,
+
+    /// Also print a histogram of the top-1 and top-k scores.
+    #[arg(long)]
+    score_histogram: bool,
+
+    /// Number of equal-width histogram bins over [0, 1].
+    #[arg(long, default_value_t = 10)]
+    bins: usize,
+
+    /// Output as JSON to stdout.
+    #[arg(long)]
+    json: bool,
+
+    /// Number of parallel embedding workers.
+    #[arg(long, default_value = "1")]
+    workers: usize,
+
+    /// Cache size per worker.
+    #[arg(long, default_value = "1000")]
+    cache_size: usize,
+}
+
 /// Application entry point.
 fn main() -> Result<()> {
     let cli = Cli::parse();
@@ -376,12 +648,18 @@ fn main() -> Result<()> {
         Commands::Mcp(args) => run_mcp(args),
         Commands::Hotspots(args) => run_hotspots(args),
         Commands::History(args) => run_history(args),
+        Commands::Clusters(args) => run_clusters(args),
+        Commands::Export(args) => run_export(args),
+        Commands::Diff(args) => run_diff(args),
+        Commands::Reingest(args) => run_reingest(args),
+        Commands::Embed(args) => run_embed(args),
+        Commands::Stats(args) => run_stats(args),
     }
 }
 
 /// Run the `mcp` subcommand.
 fn run_mcp(args: McpArgs) -> Result<()> {
-    run_mcp_server(&args.smg)
+    run_mcp_server(&args.smg, args.query_cache)
 }
 
 /// Run the `hotspots` subcommand.
@@ -433,11 +711,13 @@ fn run_update(args: UpdateArgs) -> Result<()> {
         append: true,
         include_diff: false,
         max_commits: args.max_commits,
+        max_notes: args.max_notes,
         workers: args.workers,
         cache_size: args.cache_size,
         git_filter_drop: args.git_filter_drop,
         git_filter_preset: args.git_filter_preset,
         git_filter_case_insensitive: args.git_filter_case_insensitive,
+        merge_commit_policy: args.merge_commit_policy,
         incremental: true,
         git_commit_split_mode: args.git_commit_split_mode,
         git_commit_split_max_segments: args.git_commit_split_max_segments,
@@ -445,10 +725,207 @@ fn run_update(args: UpdateArgs) -> Result<()> {
         num_spectral_dims: args.num_spectral_dims,
         min_clusters: args.min_clusters,
         max_clusters: args.max_clusters,
+        max_links: args.max_links,
+        dedup_exact: args.dedup_exact,
+        persist_similarity: args.persist_similarity,
     };
     run_ingest(ingest_args)
 }
 
+/// JSON payload printed by `embed --format json`.
+fn embedding_json(embedding: &[f32]) -> serde_json::Value {
+    let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    json!({
+        "dim": embedding.len(),
+        "norm": norm,
+        "embedding": embedding,
+    })
+}
+
+/// Encode a vector as a 1-D little-endian `float32` `.npy` file (format version 1.0).
+fn npy_bytes(values: &[f32]) -> Vec<u8> {
+    let mut header = format!(
+        "{{'descr': '<f4', 'fortran_order': False, 'shape': ({},), }}",
+        values.len()
+    );
+    // Magic (6) + version (2) + header length (2) + header must be a multiple of 64,
+    // with the header terminated by a newline.
+    let unpadded = 10 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(10 + header.len() + values.len() * 4);
+    out.extend_from_slice(b"\x93NUMPY\x01\x00");
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    for v in values {
+        out.extend_from_slice(&v.to_le_bytes());
+    }
+    out
+}
+
+/// Run the `embed` subcommand.
+fn run_embed(args: EmbedArgs) -> Result<()> {
+    let text = match (&args.text, &args.file) {
+        (Some(text), _) => text.clone(),
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .with_context(|| format!("reading {}", path.display()))?,
+        (None, None) => anyhow::bail!("one of --text or --file is required"),
+    };
+    let npy_out = match args.format.to_lowercase().as_str() {
+        "json" => None,
+        "npy" => Some(args.out.clone().context("--format npy requires --out <PATH>")?),
+        other => anyhow::bail!("invalid --format '{}'; expected json|npy", other),
+    };
+
+    embed::init(args.workers, args.cache_size).with_context(|| "initializing embedding pool")?;
+    let _guard = scopeguard::guard((), |_| {
+        let _ = embed::shutdown();
+    });
+    let embedding = embed::get_embedding(&text).with_context(|| "embedding text")?;
+
+    match npy_out {
+        None => println!("{}", serde_json::to_string_pretty(&embedding_json(&embedding))?),
+        Some(path) => {
+            std::fs::write(&path, npy_bytes(&embedding))
+                .with_context(|| format!("writing {}", path.display()))?;
+            let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+            println!(
+                "Wrote {}-dim embedding to {} (norm={})",
+                embedding.len(),
+                path.display(),
+                norm
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Run the `stats` subcommand.
+fn run_stats(args: StatsArgs) -> Result<()> {
+    if args.score_histogram && args.bins == 0 {
+        anyhow::bail!("--bins must be at least 1 with --score-histogram");
+    }
+    let queries: Vec<String> = std::fs::read_to_string(&args.queries_file)
+        .with_context(|| format!("reading {}", args.queries_file.display()))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if queries.is_empty() {
+        anyhow::bail!("no queries found in {}", args.queries_file.display());
+    }
+    let smg = load_smg_json(&args.smg)
+        .with_context(|| format!("loading SMG from {}", args.smg.display()))?;
+
+    embed::init(args.workers, args.cache_size).with_context(|| "initializing embedding pool")?;
+    let _guard = scopeguard::guard((), |_| {
+        let _ = embed::shutdown();
+    });
+    let scores = crate::score_stats::collect_query_scores(&smg, &queries, args.top_k)?;
+
+    let bins = if args.score_histogram { args.bins } else { 0 };
+    let top1 = crate::score_stats::summarize_scores(&scores.top1, bins);
+    let topk = crate::score_stats::summarize_scores(&scores.topk, bins);
+
+    if args.json {
+        let out = json!({
+            "smg": args.smg.to_string_lossy().to_string(),
+            "queries": queries.len(),
+            "empty_queries": scores.empty_queries,
+            "top_k": args.top_k,
+            "top1": top1,
+            "topk": topk,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    println!(
+        "Queries: {} ({} without results), top_k={}",
+        queries.len(),
+        scores.empty_queries,
+        args.top_k
+    );
+    for line in crate::score_stats::format_summary("Top-1", &top1, 40) {
+        println!("{}", line);
+    }
+    for line in crate::score_stats::format_summary("Top-k", &topk, 40) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Run the `reingest` subcommand.
+fn run_reingest(args: ReingestArgs) -> Result<()> {
+    let outp = args.out;
+    let mut smg = load_smg_json(&outp)
+        .with_context(|| format!("loading SMG from {}", outp.display()))?;
+    // Keep a persisted similarity matrix persisted across the rewrite.
+    let save_options = SmgSaveOptions {
+        include_similarity_matrix: args.persist_similarity || smg.similarity_matrix.is_some(),
+    };
+
+    embed::init(args.workers, args.cache_size).with_context(|| "initializing embedding pool")?;
+    let _guard = scopeguard::guard((), |_| {
+        let _ = embed::shutdown();
+    });
+
+    // Reuse the ingest option parsing for filters and split configuration.
+    let commit = args.commit;
+    let ingest_args = IngestArgs {
+        repo: args.repo,
+        out: Some(outp.clone()),
+        append: true,
+        include_diff: false,
+        max_commits: None,
+        max_notes: None,
+        workers: args.workers,
+        cache_size: args.cache_size,
+        git_filter_drop: args.git_filter_drop,
+        git_filter_preset: args.git_filter_preset,
+        git_filter_case_insensitive: args.git_filter_case_insensitive,
+        merge_commit_policy: args.merge_commit_policy,
+        incremental: false,
+        git_commit_split_mode: args.git_commit_split_mode,
+        git_commit_split_max_segments: args.git_commit_split_max_segments,
+        git_commit_split_min_confidence: args.git_commit_split_min_confidence,
+        num_spectral_dims: None,
+        min_clusters: None,
+        max_clusters: None,
+        max_links: None,
+        dedup_exact: false,
+        persist_similarity: save_options.include_similarity_matrix,
+    };
+    let filters = GitFilterConfig::from_ingest_args(&ingest_args)?;
+    let split_config = CommitSplitConfig::from_ingest_args(&ingest_args)?;
+    let registry = crate::ast::registry::ParserRegistry::new();
+
+    let (commit_id, turns) =
+        collect_commit_turns(&ingest_args.repo, &commit, &filters, &split_config, &registry)
+            .with_context(|| format!("collecting commit {}", commit))?;
+    let (previous, new_ids) = smg
+        .reingest_commit(&commit_id, &turns)
+        .with_context(|| format!("re-ingesting commit {}", commit_id))?;
+    println!(
+        "Re-ingested commit {}: {} notes replaced by {} (mode={}).",
+        commit_id,
+        previous,
+        new_ids.len(),
+        split_config.mode.as_str()
+    );
+
+    let config = smg.last_build_config.clone().unwrap_or_default();
+    smg.build_spectral_structure_with_config(None, &config)
+        .context("building spectral structures")?;
+
+    save_smg_json_with_options(&smg, &outp, &save_options)
+        .with_context(|| format!("saving SMG to {}", outp.display()))?;
+    println!("Saved SMG to {}", outp.display());
+    Ok(())
+}
+
 /// Run the `ingest` subcommand.
 ///
 /// This function:
@@ -462,6 +939,9 @@ fn run_update(args: UpdateArgs) -> Result<()> {
 /// Returns an `anyhow::Error` when IO/git operations fail or when the library API fails.
 fn run_ingest(args: IngestArgs) -> Result<()> {
     println!("Starting ingest for repo: {}", args.repo.display());
+    let save_options = SmgSaveOptions {
+        include_similarity_matrix: args.persist_similarity,
+    };
 
     // Initialize embedding pool asynchronously to overlap with commit collection
     println!(
@@ -498,14 +978,16 @@ fn run_ingest(args: IngestArgs) -> Result<()> {
             (after as f64 / before as f64) * 100.0
         };
         println!(
-            "Git filter summary: seen={} kept={} skipped={} dropped_lines={} chars_before={} chars_after={} ({:.1}% retained)",
+            "Git filter summary: seen={} kept={} skipped={} dropped_lines={} chars_before={} chars_after={} ({:.1}% retained) merges_skipped={} merges_subject_only={}",
             collected.filter_stats.total_commits_seen,
             collected.filter_stats.commits_kept,
             collected.filter_stats.commits_skipped_empty,
             collected.filter_stats.lines_dropped,
             before,
             after,
-            ratio
+            ratio,
+            collected.filter_stats.merge_commits_skipped,
+            collected.filter_stats.merge_commits_subject_only
         );
     }
     println!(
@@ -577,6 +1059,16 @@ fn run_ingest(args: IngestArgs) -> Result<()> {
         );
     }
 
+    if let Some(max_notes) = args.max_notes {
+        let dropped = cap_turns_to_max_notes(&mut turns, smg.notes.len(), max_notes);
+        if dropped > 0 {
+            println!(
+                "Note cap: --max-notes {} reached, {} remaining turns not ingested.",
+                max_notes, dropped
+            );
+        }
+    }
+
     // Ensure globally unique turn IDs across repeated append/update runs.
     let max_existing_turn_id = smg
         .notes
@@ -591,7 +1083,7 @@ fn run_ingest(args: IngestArgs) -> Result<()> {
     if turns.is_empty() {
         println!("No new turns to ingest.");
         if let Some(outp) = args.out {
-            save_smg_json(&smg, &outp)
+            save_smg_json_with_options(&smg, &outp, &save_options)
                 .with_context(|| format!("saving SMG to {}", outp.display()))?;
             println!("Saved SMG to {}", outp.display());
         }
@@ -633,7 +1125,8 @@ fn run_ingest(args: IngestArgs) -> Result<()> {
         .map_err(|_| anyhow::anyhow!("embedding pool initialization thread panicked"))?
         .with_context(|| "initializing embedding pool (background join)")?;
 
-    smg.ingest_turns_batch(&turns, Some(progress_cb))
+    let notes_before = smg.notes.len();
+    smg.ingest_turns_batch_with_dedup(&turns, Some(progress_cb), args.dedup_exact)
         .with_context(|| "batch embedding turns")?;
 
     // Post-ingestion: populate structural links based on symbol_id.
@@ -643,6 +1136,10 @@ fn run_ingest(args: IngestArgs) -> Result<()> {
     smg.resolve_structural_links();
 
     ingest_bar.finish_with_message(format!("Ingested {} turns into the SMG.", smg.notes.len()));
+    if args.dedup_exact {
+        let merged = turns.len().saturating_sub(smg.notes.len() - notes_before);
+        println!("Exact dedup: {} duplicate turns merged into existing notes.", merged);
+    }
 
     // Always rebuild spectral structures with progress bar.
     let spectral_bar = ProgressBar::new(10);
@@ -668,6 +1165,7 @@ fn run_ingest(args: IngestArgs) -> Result<()> {
     if let Some(n) = args.num_spectral_dims { config.num_spectral_dims = n; }
     if let Some(n) = args.min_clusters { config.min_clusters = n; }
     if let Some(n) = args.max_clusters { config.max_clusters = n; }
+    if let Some(n) = args.max_links { config.max_links = Some(n); }
 
     smg.build_spectral_structure_with_config(Some(progress_cb), &config)
         .context("building spectral structures")?;
@@ -677,7 +1175,8 @@ fn run_ingest(args: IngestArgs) -> Result<()> {
     if let Some(outp) = args.out {
         let start_ser = Instant::now();
         println!("Serializing SMG to {}...", outp.display());
-        save_smg_json(&smg, &outp).with_context(|| format!("saving SMG to {}", outp.display()))?;
+        save_smg_json_with_options(&smg, &outp, &save_options)
+            .with_context(|| format!("saving SMG to {}", outp.display()))?;
         println!("Saved SMG to {} in {:?}", outp.display(), start_ser.elapsed());
     }
 
@@ -697,6 +1196,25 @@ fn run_ingest(args: IngestArgs) -> Result<()> {
     Ok(())
 }
 
+/// Drop trailing turns so that ingesting the rest cannot grow the graph beyond `max_notes`.
+///
+/// Turns arrive most recent first, so the newest turns are kept. Every kept turn is counted
+/// as a new note, which keeps the cap exact without dedup and conservative with it.
+///
+/// # Returns
+///
+/// The number of dropped turns.
+fn cap_turns_to_max_notes(
+    turns: &mut Vec<ConversationTurn>,
+    existing_notes: usize,
+    max_notes: usize,
+) -> usize {
+    let budget = max_notes.saturating_sub(existing_notes);
+    let before = turns.len();
+    turns.truncate(budget);
+    before - turns.len()
+}
+
 #[derive(Debug, Default)]
 struct GitFilterStats {
     total_commits_seen: usize,
@@ -705,6 +1223,8 @@ struct GitFilterStats {
     lines_dropped: usize,
     total_chars_before: usize,
     total_chars_after: usize,
+    merge_commits_skipped: usize,
+    merge_commits_subject_only: usize,
 }
 
 impl GitFilterStats {
@@ -716,6 +1236,36 @@ impl GitFilterStats {
         self.lines_dropped = self.lines_dropped.saturating_add(other.lines_dropped);
         self.total_chars_before = self.total_chars_before.saturating_add(other.total_chars_before);
         self.total_chars_after = self.total_chars_after.saturating_add(other.total_chars_after);
+        self.merge_commits_skipped =
+            self.merge_commits_skipped.saturating_add(other.merge_commits_skipped);
+        self.merge_commits_subject_only = self
+            .merge_commits_subject_only
+            .saturating_add(other.merge_commits_subject_only);
+    }
+}
+
+/// How commits with more than one parent are ingested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MergeCommitPolicy {
+    /// Ingest merge commits like any other commit.
+    Include,
+    /// Do not ingest merge commits.
+    Skip,
+    /// Ingest only the subject line of merge commits.
+    SubjectOnly,
+}
+
+impl MergeCommitPolicy {
+    fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "include" => Ok(Self::Include),
+            "skip" => Ok(Self::Skip),
+            "subject-only" => Ok(Self::SubjectOnly),
+            other => Err(anyhow::anyhow!(
+                "unsupported --merge-commit-policy '{}'; supported: include|skip|subject-only",
+                other
+            )),
+        }
     }
 }
 
@@ -723,6 +1273,7 @@ impl GitFilterStats {
 struct GitFilterConfig {
     drop_patterns: Vec<Regex>,
     html_comment_regex: Regex,
+    merge_commit_policy: MergeCommitPolicy,
 }
 
 impl GitFilterConfig {
@@ -770,6 +1321,7 @@ impl GitFilterConfig {
         Ok(Self {
             drop_patterns,
             html_comment_regex,
+            merge_commit_policy: MergeCommitPolicy::parse(&args.merge_commit_policy)?,
         })
     }
 }
@@ -848,8 +1400,110 @@ fn apply_git_line_filters(
     Some(filtered)
 }
 
+/// Output format of the `query` subcommand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueryOutputFormat {
+    Human,
+    Json,
+    /// Bare ranked ids, one per line, for shell pipelines.
+    Ids,
+}
+
+impl QueryOutputFormat {
+    fn from_query_args(args: &QueryArgs) -> Result<Self> {
+        if args.json {
+            return Ok(Self::Json);
+        }
+        match args.format.to_lowercase().as_str() {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "ids" => Ok(Self::Ids),
+            other => Err(anyhow::anyhow!(
+                "unsupported --format '{}'; supported: human|json|ids",
+                other
+            )),
+        }
+    }
+}
+
+/// Which id `--format ids` emits per result.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QueryIdKind {
+    Turn,
+    Note,
+}
+
+impl QueryIdKind {
+    fn parse(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "turn" => Ok(Self::Turn),
+            "note" => Ok(Self::Note),
+            other => Err(anyhow::anyhow!(
+                "unsupported --id-kind '{}'; supported: turn|note",
+                other
+            )),
+        }
+    }
+}
+
+/// Render ranked query results as bare ids, one entry per line.
+///
+/// Turn ids are emitted in rank order. Note ids are emitted in the rank order of their best
+/// turn, each note only once; turns without an owning note are skipped.
+fn format_query_ids(
+    smg: &SpectralMemoryGraph,
+    results: &[(u64, f32)],
+    id_kind: QueryIdKind,
+) -> Vec<String> {
+    match id_kind {
+        QueryIdKind::Turn => results.iter().map(|(tid, _)| tid.to_string()).collect(),
+        QueryIdKind::Note => {
+            let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
+            note_ids.sort_unstable();
+            // Map each turn to the lowest-id note containing it, once for all results.
+            let mut note_by_turn: HashMap<u64, u32> = HashMap::new();
+            for nid in &note_ids {
+                for tid in &smg.notes[nid].source_turn_ids {
+                    note_by_turn.entry(*tid).or_insert(*nid);
+                }
+            }
+            let mut seen: HashSet<u32> = HashSet::new();
+            results
+                .iter()
+                .filter_map(|(tid, _)| note_by_turn.get(tid).copied())
+                .filter(|nid| seen.insert(*nid))
+                .map(|nid| nid.to_string())
+                .collect()
+        }
+    }
+}
+
+/// Parse a time argument given as an RFC3339 string or bare epoch seconds.
+///
+/// A value made only of ASCII digits is taken as Unix epoch seconds; anything else
+/// must be RFC3339 (e.g. `2024-05-01T00:00:00Z`).
+fn parse_time_arg(flag: &str, value: &str) -> Result<u64> {
+    let trimmed = value.trim();
+    if !trimmed.is_empty() && trimmed.bytes().all(|b| b.is_ascii_digit()) {
+        return trimmed
+            .parse::<u64>()
+            .with_context(|| format!("Failed to parse {} as epoch seconds: {}", flag, value));
+    }
+    let dt = chrono::DateTime::parse_from_rfc3339(trimmed).with_context(|| {
+        format!(
+            "Failed to parse {} as RFC3339 or epoch seconds: {}",
+            flag, value
+        )
+    })?;
+    u64::try_from(dt.timestamp())
+        .map_err(|_| anyhow::anyhow!("{} must not be before 1970-01-01: {}", flag, value))
+}
+
 /// Run the `query` subcommand.
 fn run_query(args: QueryArgs) -> Result<()> {
+    let output_format = QueryOutputFormat::from_query_args(&args)?;
+    let id_kind = QueryIdKind::parse(&args.id_kind)?;
+
     // Initialize embedding pool with CLI parameters
     let _start_total = Instant::now();
     let _ = _start_total; // suppress unused warning
@@ -873,30 +1527,24 @@ fn run_query(args: QueryArgs) -> Result<()> {
     let candidate_k = args.candidate_k.unwrap_or(args.top_k.saturating_mul(5));
 
     // Parse temporal now if provided.
-    let now_seconds_override = if let Some(now_str) = args.temporal_now.as_ref() {
-        let dt = chrono::DateTime::parse_from_rfc3339(now_str)
-            .with_context(|| format!("Failed to parse --temporal-now as RFC3339: {}", now_str))?;
-        Some(dt.timestamp() as u64)
-    } else {
-        None
-    };
+    let now_seconds_override = args
+        .temporal_now
+        .as_deref()
+        .map(|v| parse_time_arg("--temporal-now", v))
+        .transpose()?;
 
     // Parse time filtering arguments.
-    let time_start_seconds = if let Some(start_str) = args.time_start.as_ref() {
-        let dt = chrono::DateTime::parse_from_rfc3339(start_str)
-            .with_context(|| format!("Failed to parse --time-start as RFC3339: {}", start_str))?;
-        Some(dt.timestamp() as u64)
-    } else {
-        None
-    };
-
-    let _time_end_seconds = if let Some(end_str) = args.time_end.as_ref() {
-        let dt = chrono::DateTime::parse_from_rfc3339(end_str)
-            .with_context(|| format!("Failed to parse --time-end as RFC3339: {}", end_str))?;
-        Some(dt.timestamp() as u64)
-    } else {
-        None
-    };
+    let time_start_seconds = args
+        .time_start
+        .as_deref()
+        .map(|v| parse_time_arg("--time-start", v))
+        .transpose()?;
+
+    let _time_end_seconds = args
+        .time_end
+        .as_deref()
+        .map(|v| parse_time_arg("--time-end", v))
+        .transpose()?;
 
     // Compute time window if provided.
     let time_window_start_seconds = if let Some(window_days) = args.time_window_days {
@@ -922,6 +1570,17 @@ fn run_query(args: QueryArgs) -> Result<()> {
         "buckets" => TemporalMode::Buckets,
         _ => TemporalMode::Exponential,
     };
+    let timestamp_source = match args.temporal_source.to_lowercase().as_str() {
+        "commit" => TimestampSource::Commit,
+        "ingest" => TimestampSource::Ingest,
+        "blend" => TimestampSource::Blend {
+            ingest_weight: args.temporal_ingest_weight,
+        },
+        other => anyhow::bail!(
+            "invalid --temporal-source '{}'; expected commit|ingest|blend",
+            other
+        ),
+    };
 
     let tcfg = TemporalConfig {
         enabled: !args.no_temporal,
@@ -932,6 +1591,7 @@ fn run_query(args: QueryArgs) -> Result<()> {
         boost_magnitude: None,
         buckets: None,
         now_seconds: now_seconds_override,
+        timestamp_source,
     };
 
     let start_retrieve = Instant::now();
@@ -979,104 +1639,14 @@ fn run_query(args: QueryArgs) -> Result<()> {
     // Use `final_results` as the unified list used by both JSON and human output paths.
     let final_results = scored;
 
-    if args.json {
-        // Produce a JSON payload including note content, metadata and score for each returned turn.
-        // Prepare grouped results by commit
-        let mut primary_results: Vec<(String, serde_json::Value)> = Vec::new();
-        let mut fallback_results: Vec<serde_json::Value> = Vec::new();
-        let mut seen_commits: std::collections::HashSet<String> = std::collections::HashSet::new();
-
-        // Prepare a deterministic ordering of notes to map cluster labels (if present).
-        let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
-        note_ids.sort_unstable();
-
-        for (tid, score) in final_results.iter() {
-            // Find a note that contains this turn id.
-            let mut found: Option<(u32, &spectral_cortex::model::smg_note::SMGNote)> = None;
-            for nid in note_ids.iter() {
-                if let Some(note) = smg.notes.get(nid) {
-                    if note.source_turn_ids.contains(tid) {
-                        found = Some((*nid, note));
-                        break;
-                    }
-                }
-            }
-            if let Some((nid, note)) = found {
-                // Find commit id corresponding to this turn (if present).
-                let commit_id_for_turn: Option<String> = note
-                    .source_turn_ids
-                    .iter()
-                    .position(|x| x == tid)
-                    .and_then(|idx| note.source_commit_ids.get(idx).cloned().flatten());
-
-                // Base object for the note (include score and commit id).
-                let related_notes: Vec<serde_json::Value> = smg
-                    .get_related_note_links(nid, args.links_k.or(Some(5)))
-                    .into_iter()
-                    .map(|(related_nid, sim)| {
-                        serde_json::json!({
-                            "note_id": related_nid,
-                            "spectral_similarity": sim
-                        })
-                    })
-                    .collect();
-                let mut obj = serde_json::json!({
-                    "turn_id": tid,
-                    "note_id": nid,
-                    "score": score,
-                    "commit_id": commit_id_for_turn,
-                    "symbol_id": note.symbol_id,
-                    "ast_node_type": note.ast_node_type,
-                    "file_path": note.file_path,
-                    "raw_content": note.raw_content,
-                    "context": note.context(),
-                    "source_turn_ids": note.source_turn_ids,
-                    "related_notes": related_notes,
-                });
-                
-                // If cluster labels are present, map the note id to its label using the sorted ordering.
-                if let Some(labels) = smg.cluster_labels.as_ref() {
-                    if let Some(idx) = note_ids.iter().position(|x| x == &nid) {
-                        if let Some(lbl) = labels.get(idx) {
-                            // Insert cluster label into the JSON object.
-                            if let Some(map) = obj.as_object_mut() {
-                                map.insert(
-                                    "cluster_label".to_string(),
-                                    serde_json::Value::from(*lbl),
-                                );
-                            }
-                        }
-                    }
-                }
-
-                // Group by commit ID if present
-                if let Some(ref cid) = commit_id_for_turn {
-                    if !seen_commits.contains(cid) {
-                        seen_commits.insert(cid.clone());
-                        // Initialize contextual hits array
-                        if let Some(map) = obj.as_object_mut() {
-                            map.insert("contextual_hits".to_string(), serde_json::json!([]));
-                        }
-                        primary_results.push((cid.clone(), obj));
-                    } else {
-                        // Push to the primary result's contextual_hits array
-                        if let Some((_, primary_obj)) = primary_results.iter_mut().find(|(c, _)| c == cid) {
-                            if let Some(hits) = primary_obj.get_mut("contextual_hits").and_then(|h| h.as_array_mut()) {
-                                hits.push(obj);
-                            }
-                        }
-                    }
-                } else {
-                    fallback_results.push(obj);
-                }
-            } else {
-                // No associated note found; include the turn id and score only.
-                fallback_results.push(serde_json::json!({ "turn_id": tid, "score": score }));
-            }
+    if output_format == QueryOutputFormat::Ids {
+        for line in format_query_ids(&smg, &final_results, id_kind) {
+            println!("{}", line);
         }
-        
-        let mut results: Vec<serde_json::Value> = primary_results.into_iter().map(|(_, obj)| obj).collect();
-        results.extend(fallback_results);
+    } else if output_format == QueryOutputFormat::Json {
+        // Produce a JSON payload including note content, metadata and score for each
+        // returned turn, grouped by commit.
+        let results = build_query_results(&smg, &final_results, args.links_k.or(Some(5)));
         // Echo the effective temporal configuration in the JSON output.
         let temporal_info = json!({
             "enabled": !args.no_temporal,
@@ -1084,6 +1654,7 @@ fn run_query(args: QueryArgs) -> Result<()> {
             "mode": args.temporal_mode,
             "half_life_days": args.temporal_half_life_days,
             "now": args.temporal_now,
+            "source": tcfg.timestamp_source,
         });
 
         // Get long-range links if requested
@@ -1111,42 +1682,23 @@ fn run_query(args: QueryArgs) -> Result<()> {
     } else {
         println!("Top {} matching results for query {:?}:", args.top_k, q);
         // Print a short human-readable snippet per result, including score when available.
-        let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
-        note_ids.sort_unstable();
+        let turn_index = turn_note_index(&smg);
         for (i, (tid, score)) in final_results.iter().enumerate() {
-            // Attempt to find the note containing this turn id to show a snippet.
-            let mut snippet: Option<String> = None;
-            let mut note_id_opt: Option<u32> = None;
-            let mut commit_for_tid: Option<String> = None;
-            for nid in note_ids.iter() {
-                if let Some(note) = smg.notes.get(nid) {
-                    if note.source_turn_ids.contains(tid) {
-                        let raw = &note.raw_content;
-                        let sn = if raw.len() > 120 {
-                            format!("{}...", &raw[..120])
-                        } else {
-                            raw.clone()
-                        };
-                        snippet = Some(sn);
-                        note_id_opt = Some(*nid);
-                        // Compute commit id corresponding to this turn if available.
-                        commit_for_tid = note
-                            .source_turn_ids
-                            .iter()
-                            .position(|x| x == tid)
-                            .and_then(|idx| note.source_commit_ids.get(idx).cloned().flatten());
-                        break;
-                    }
-                }
-            }
-            if let Some(nid) = note_id_opt {
-                if let Some(sn) = snippet {
-                    if let Some(cid) = &commit_for_tid {
+            // Related notes are not shown here, so skip collecting them.
+            match query_result_for_turn(&smg, &turn_index, *tid, *score, Some(0)) {
+                QueryResultEntry::Note(hit) => {
+                    let raw = &hit.raw_content;
+                    let sn = if raw.len() > 120 {
+                        format!("{}...", &raw[..120])
+                    } else {
+                        raw.clone()
+                    };
+                    if let Some(cid) = &hit.commit_id {
                         println!(
                             "{}. turn_id={} note_id={} commit_id={} score={} snippet: {}",
                             i + 1,
                             tid,
-                            nid,
+                            hit.note_id,
                             cid,
                             score,
                             sn
@@ -1156,16 +1708,15 @@ fn run_query(args: QueryArgs) -> Result<()> {
                             "{}. turn_id={} note_id={} score={} snippet: {}",
                             i + 1,
                             tid,
-                            nid,
+                            hit.note_id,
                             score,
                             sn
                         );
                     }
-                } else {
+                }
+                QueryResultEntry::Unresolved(_) => {
                     println!("{}. turn_id={} score={}", i + 1, tid, score);
                 }
-            } else {
-                println!("{}. turn_id={} score={}", i + 1, tid, score);
             }
         }
 
@@ -1208,6 +1759,20 @@ fn run_note(args: NoteArgs) -> Result<()> {
 
     let related = smg.get_related_note_links(args.note_id, args.links_k);
 
+    let path = match args.path_to {
+        Some(target) => {
+            if !smg.notes.contains_key(&target) {
+                return Err(anyhow::anyhow!(
+                    "--path-to note_id {} not found (SMG contains {} notes)",
+                    target,
+                    smg.notes.len()
+                ));
+            }
+            Some((target, smg.link_path(args.note_id, target)))
+        }
+        None => None,
+    };
+
     if args.json {
         let related_json: Vec<serde_json::Value> = related
             .iter()
@@ -1228,7 +1793,7 @@ fn run_note(args: NoteArgs) -> Result<()> {
             })
             .collect();
 
-        let out = json!({
+        let mut out = json!({
             "smg": args.smg.to_string_lossy().to_string(),
             "note": {
                 "note_id": note.note_id,
@@ -1236,10 +1801,15 @@ fn run_note(args: NoteArgs) -> Result<()> {
                 "raw_content": note.raw_content,
                 "source_turn_ids": note.source_turn_ids,
                 "source_commit_ids": note.source_commit_ids,
+                "segment_index": note.segment_index,
+                "segment_count": note.segment_count,
                 "cluster_label": cluster_label,
             },
             "related_notes": related_json
         });
+        if let Some((target, path)) = &path {
+            out["path_to"] = json!({ "note_id": target, "path": path });
+        }
 
         println!("{}", serde_json::to_string_pretty(&out)?);
     } else {
@@ -1248,6 +1818,9 @@ fn run_note(args: NoteArgs) -> Result<()> {
             println!("cluster_label={}", lbl);
         }
         println!("source_turn_ids={:?}", note.source_turn_ids);
+        if let (Some(index), Some(count)) = (note.segment_index, note.segment_count) {
+            println!("segment={}/{}", index + 1, count);
+        }
         println!("context: {}", note.context());
         let snippet = if note.raw_content.len() > 200 {
             format!("{}...", &note.raw_content[..200])
@@ -1279,11 +1852,317 @@ fn run_note(args: NoteArgs) -> Result<()> {
                 }
             }
         }
+
+        match &path {
+            Some((target, Some(path))) => {
+                let hops: Vec<String> = path.iter().map(|id| id.to_string()).collect();
+                println!("\nLink path to {}: {}", target, hops.join(" -> "));
+            }
+            Some((target, None)) => println!("\nNo link path to {}.", target),
+            None => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Count notes per cluster label, ordered by label.
+fn cluster_sizes(smg: &SpectralMemoryGraph) -> Vec<(usize, usize)> {
+    let mut sizes: std::collections::BTreeMap<usize, usize> = std::collections::BTreeMap::new();
+    if let Some(labels) = &smg.cluster_labels {
+        for lbl in labels.iter() {
+            *sizes.entry(*lbl).or_insert(0) += 1;
+        }
+    }
+    sizes.into_iter().collect()
+}
+
+/// Run the `clusters` subcommand.
+fn run_clusters(args: ClustersArgs) -> Result<()> {
+    let smg = load_smg_json(&args.smg)
+        .with_context(|| format!("loading SMG from {}", args.smg.display()))?;
+
+    let sizes = cluster_sizes(&smg);
+    if sizes.is_empty() {
+        anyhow::bail!(
+            "SMG {} has no cluster labels; run ingest to build spectral structures first",
+            args.smg.display()
+        );
+    }
+    let relationships = if args.relationships {
+        Some(smg.cluster_centroid_similarity().ok_or_else(|| {
+            anyhow::anyhow!("SMG {} has no cluster centroids", args.smg.display())
+        })?)
+    } else {
+        None
+    };
+
+    if args.json {
+        let clusters_json: Vec<serde_json::Value> = sizes
+            .iter()
+            .map(|(label, size)| {
+                let terms: Vec<serde_json::Value> = smg
+                    .cluster_top_terms(*label, args.terms)
+                    .into_iter()
+                    .map(|(term, weight)| json!({ "term": term, "weight": weight }))
+                    .collect();
+                json!({ "label": label, "size": size, "top_terms": terms })
+            })
+            .collect();
+        let mut out = json!({
+            "smg": args.smg.to_string_lossy().to_string(),
+            "clusters": clusters_json,
+        });
+        if let Some((labels, sim)) = &relationships {
+            let rows: Vec<Vec<f32>> = sim.outer_iter().map(|row| row.to_vec()).collect();
+            out["relationships"] = json!({ "labels": labels, "similarity": rows });
+        }
+        println!("{}", serde_json::to_string_pretty(&out)?);
+    } else {
+        println!("Clusters ({}):", sizes.len());
+        for (label, size) in &sizes {
+            let terms: Vec<String> = smg
+                .cluster_top_terms(*label, args.terms)
+                .into_iter()
+                .map(|(term, _)| term)
+                .collect();
+            if terms.is_empty() {
+                println!("  cluster={} notes={}", label, size);
+            } else {
+                println!("  cluster={} notes={} terms={}", label, size, terms.join(","));
+            }
+        }
+        if let Some((labels, sim)) = &relationships {
+            println!("\nCentroid similarity:");
+            let header: Vec<String> = labels.iter().map(|l| format!("{:>7}", l)).collect();
+            println!("{:>7} {}", "", header.join(" "));
+            for (label, row) in labels.iter().zip(sim.outer_iter()) {
+                let cells: Vec<String> = row.iter().map(|v| format!("{:>7.3}", v)).collect();
+                println!("{:>7} {}", label, cells.join(" "));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the `export` subcommand.
+fn run_export(args: ExportArgs) -> Result<()> {
+    let smg = load_smg_json(&args.smg)
+        .with_context(|| format!("loading SMG from {}", args.smg.display()))?;
+
+    match args.format.to_lowercase().as_str() {
+        "labels-csv" => {
+            let rows = export_labels_csv(&smg, &args.out)
+                .with_context(|| format!("writing labels to {}", args.out.display()))?;
+            if rows == 0 && !smg.notes.is_empty() {
+                eprintln!(
+                    "Warning: SMG has no cluster labels for its {} notes; wrote header only to {}",
+                    smg.notes.len(),
+                    args.out.display()
+                );
+            }
+            eprintln!("Wrote {} cluster labels to {}", rows, args.out.display());
+        }
+        "smg" => {
+            if args.clusters.is_empty() {
+                return Err(anyhow::anyhow!("--format smg requires --clusters <LABELS>"));
+            }
+            save_smg_json_clusters(&smg, &args.out, &args.clusters)
+                .with_context(|| format!("writing filtered SMG to {}", args.out.display()))?;
+            eprintln!("Wrote clusters {:?} to {}", args.clusters, args.out.display());
+        }
+        other => {
+            return Err(anyhow::anyhow!(
+                "unsupported --format '{}'; supported: labels-csv, smg",
+                other
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// Run the `diff` subcommand.
+fn run_diff(args: DiffArgs) -> Result<()> {
+    let a = load_smg_json(&args.a)
+        .with_context(|| format!("loading SMG from {}", args.a.display()))?;
+    let b = load_smg_json(&args.b)
+        .with_context(|| format!("loading SMG from {}", args.b.display()))?;
+    let diff = crate::graph_diff::diff_graphs(&a, &b);
+
+    if args.json {
+        let out = json!({
+            "a": args.a.to_string_lossy().to_string(),
+            "b": args.b.to_string_lossy().to_string(),
+            "notes_a": a.notes.len(),
+            "notes_b": b.notes.len(),
+            "diff": diff,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    let snippet = crate::graph_diff::note_snippet;
+
+    println!("A: {} ({} notes)", args.a.display(), a.notes.len());
+    println!("B: {} ({} notes)", args.b.display(), b.notes.len());
+    println!("\nOnly in A: {}", diff.only_in_a.len());
+    for nid in &diff.only_in_a {
+        println!("  note_id={} {}", nid, snippet(&a, nid));
+    }
+    println!("\nOnly in B: {}", diff.only_in_b.len());
+    for nid in &diff.only_in_b {
+        println!("  note_id={} {}", nid, snippet(&b, nid));
+    }
+    println!("\nCluster label changes: {}", diff.label_changes.len());
+    for change in &diff.label_changes {
+        println!(
+            "  note_id a={} b={} cluster {} -> {}",
+            change.note_id_a, change.note_id_b, change.label_a, change.label_b
+        );
     }
+    println!(
+        "\nLong-range links: +{} added, -{} removed",
+        diff.links_added, diff.links_removed
+    );
 
     Ok(())
 }
 
+/// Convert the segments of one commit into turns, recording each segment's position.
+fn segment_turns(
+    segments: Vec<CommitSegment>,
+    author_name: &str,
+    commit_id: &str,
+    timestamp: u64,
+) -> Vec<ConversationTurn> {
+    let segment_count = segments.len();
+    segments
+        .into_iter()
+        .enumerate()
+        .map(|(segment_index, segment)| {
+            let mut full_content = segment.header;
+            if !segment.details.is_empty() {
+                full_content.push('\n');
+                full_content.push_str(&segment.details.join("\n"));
+            }
+            ConversationTurn {
+                turn_id: 0, // Placeholder, will be set during reduction
+                speaker: author_name.to_string(),
+                content: full_content,
+                topic: "git".to_string(),
+                entities: Vec::new(),
+                commit_id: Some(commit_id.to_string()),
+                timestamp,
+                symbol_id: segment.symbol_id,
+                ast_node_type: segment.ast_node_type,
+                file_path: segment.file_path,
+                segment_index: Some(segment_index),
+                segment_count: Some(segment_count),
+                ingested_at: None,
+            }
+        })
+        .collect()
+}
+
+/// Convert one commit into turns: apply the merge-commit policy and line filters, then split.
+///
+/// Returns no turns for skipped merges and messages that are empty after filtering.
+#[cfg(feature = "git2-backend")]
+fn commit_to_turns(
+    repo: &git2::Repository,
+    commit: &git2::Commit,
+    filters: &GitFilterConfig,
+    split_config: &CommitSplitConfig,
+    registry: &crate::ast::registry::ParserRegistry,
+    filter_stats: &mut GitFilterStats,
+    split_stats: &mut CommitSplitStats,
+) -> Result<Vec<ConversationTurn>> {
+    let author = commit.author();
+    let author_name = author.name().unwrap_or("unknown").to_string();
+    let mut message = commit.message().unwrap_or("").to_string();
+    if commit.parent_count() > 1 {
+        match filters.merge_commit_policy {
+            MergeCommitPolicy::Include => {}
+            MergeCommitPolicy::Skip => {
+                filter_stats.merge_commits_skipped += 1;
+                return Ok(Vec::new());
+            }
+            MergeCommitPolicy::SubjectOnly => {
+                filter_stats.merge_commits_subject_only += 1;
+                message = message.lines().next().unwrap_or("").to_string();
+            }
+        }
+    }
+    let Some(filtered_content) = apply_git_line_filters(&message, filters, filter_stats) else {
+        return Ok(Vec::new());
+    };
+
+    let timestamp = commit.time().seconds() as u64;
+    let commit_id = commit.id().to_string();
+
+    let segments = if split_config.mode == crate::git_commit_split::CommitSplitMode::Ast {
+        crate::git_commit_split::split_commit_with_ast(
+            repo,
+            commit,
+            &filtered_content,
+            split_config,
+            split_stats,
+            registry,
+        )?
+    } else {
+        split_commit_message(&filtered_content, split_config, split_stats)
+    };
+
+    Ok(segment_turns(segments, &author_name, &commit_id, timestamp))
+}
+
+/// Resolve and re-split a single commit for `SpectralMemoryGraph::reingest_commit`.
+///
+/// `commit_ref` may be any revision git understands (full or abbreviated SHA, tag, ...).
+///
+/// # Returns
+///
+/// The full commit id and the turns for its segments under the current filters and split
+/// settings.
+fn collect_commit_turns(
+    repo_path: &PathBuf,
+    commit_ref: &str,
+    filters: &GitFilterConfig,
+    split_config: &CommitSplitConfig,
+    registry: &crate::ast::registry::ParserRegistry,
+) -> Result<(String, Vec<ConversationTurn>)> {
+    #[cfg(feature = "git2-backend")]
+    {
+        let repo = git2::Repository::open(repo_path).with_context(|| {
+            format!("failed to open git repository at '{}'", repo_path.display())
+        })?;
+        let commit = repo
+            .revparse_single(commit_ref)
+            .and_then(|obj| obj.peel_to_commit())
+            .with_context(|| format!("resolving commit '{}'", commit_ref))?;
+        let commit_id = commit.id().to_string();
+
+        let turns = commit_to_turns(
+            &repo,
+            &commit,
+            filters,
+            split_config,
+            registry,
+            &mut GitFilterStats::default(),
+            &mut CommitSplitStats::default(),
+        )?;
+        Ok((commit_id, turns))
+    }
+
+    #[cfg(not(feature = "git2-backend"))]
+    {
+        let _ = (repo_path, commit_ref, filters, split_config, registry);
+        anyhow::bail!("git2 backend feature is not enabled. Rebuild the CLI with '--features git2-backend' or enable the default features.");
+    }
+}
+
 /// Collect commits from a git repository and convert them to `ConversationTurn`.
 ///
 /// This function uses the `git2` backend when the `git2-backend` feature is enabled.
@@ -1363,61 +2242,15 @@ fn collect_commits(
 
                 let mut local_filter_stats = GitFilterStats::default();
                 let mut local_split_stats = CommitSplitStats::default();
-
-                let author = commit.author();
-                let author_name = author.name().unwrap_or("unknown").to_string();
-                let message = commit.message().unwrap_or("").to_string();
-                let filtered_content =
-                    match apply_git_line_filters(&message, filters, &mut local_filter_stats) {
-                        Some(content) => content,
-                        None => {
-                            pb.inc(1);
-                            return Ok(ParallelBatch {
-                                turns: Vec::new(),
-                                filter_stats: local_filter_stats,
-                                split_stats: local_split_stats,
-                            });
-                        }
-                    };
-
-                let time = commit.time();
-                let timestamp = time.seconds() as u64;
-                let commit_id = oid.to_string();
-
-                let segments = if split_config.mode == crate::git_commit_split::CommitSplitMode::Ast {
-                    crate::git_commit_split::split_commit_with_ast(
-                        &local_repo,
-                        &commit,
-                        &filtered_content,
-                        split_config,
-                        &mut local_split_stats,
-                        registry,
-                    )?
-                } else {
-                    split_commit_message(&filtered_content, split_config, &mut local_split_stats)
-                };
-
-                let mut local_turns = Vec::with_capacity(segments.len());
-                for segment in segments {
-                    let mut full_content = segment.header.clone();
-                    if !segment.details.is_empty() {
-                        full_content.push('\n');
-                        full_content.push_str(&segment.details.join("\n"));
-                    }
-
-                    local_turns.push(ConversationTurn {
-                        turn_id: 0, // Placeholder, will be set during reduction
-                        speaker: author_name.clone(),
-                        content: full_content,
-                        topic: "git".to_string(),
-                        entities: Vec::new(),
-                        commit_id: Some(commit_id.clone()),
-                        timestamp,
-                        symbol_id: segment.symbol_id.clone(),
-                        ast_node_type: segment.ast_node_type.clone(),
-                        file_path: segment.file_path.clone(),
-                    });
-                }
+                let local_turns = commit_to_turns(
+                    &local_repo,
+                    &commit,
+                    filters,
+                    split_config,
+                    registry,
+                    &mut local_filter_stats,
+                    &mut local_split_stats,
+                )?;
 
                 pb.inc(1);
                 Ok(ParallelBatch {
@@ -1465,12 +2298,91 @@ mod tests {
     use super::*;
     use regex::Regex;
 
+    fn test_note(note_id: u32, turn_ids: Vec<u64>) -> spectral_cortex::SMGNote {
+        spectral_cortex::SMGNote {
+            source_commit_ids: vec![None; turn_ids.len()],
+            source_timestamps: vec![0; turn_ids.len()],
+            source_turn_ids: turn_ids,
+            ..spectral_cortex::SMGNote::new(note_id, format!("note {}", note_id), vec![1.0, 0.0])
+        }
+    }
+
+    #[test]
+    fn test_format_query_ids_emits_only_ids() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        smg.notes.insert(0, test_note(0, vec![10, 11]));
+        smg.notes.insert(1, test_note(1, vec![12]));
+        let results = vec![(11u64, 0.9f32), (12, 0.8), (10, 0.7)];
+
+        let turns = format_query_ids(&smg, &results, QueryIdKind::Turn);
+        assert_eq!(turns.len(), results.len());
+        assert!(turns.iter().all(|line| line.parse::<u64>().is_ok()));
+        assert_eq!(turns.join("\n"), "11\n12\n10");
+
+        let notes = format_query_ids(&smg, &results, QueryIdKind::Note);
+        assert_eq!(notes, vec!["0", "1"]);
+    }
+
+    #[test]
+    fn test_max_notes_caps_split_turns() {
+        let cli = Cli::try_parse_from([
+            "spectral-cortex",
+            "ingest",
+            "--git-commit-split-mode",
+            "strict",
+            "--max-notes",
+            "7",
+        ])
+        .unwrap();
+        let Commands::Ingest(args) = cli.command else {
+            panic!("expected ingest command");
+        };
+        let split_config = CommitSplitConfig::from_ingest_args(&args).unwrap();
+        let mut stats = CommitSplitStats::default();
+
+        // Each commit splits into four segments, so five commits yield twenty turns.
+        let message = "feat: one\nfix: two\ndocs: three\ntest: four";
+        let mut turns: Vec<ConversationTurn> = (0..5)
+            .flat_map(|c| {
+                split_commit_message(message, &split_config, &mut stats)
+                    .into_iter()
+                    .map(move |seg| ConversationTurn {
+                        turn_id: 0,
+                        speaker: "tester".to_string(),
+                        content: seg.header,
+                        topic: "git".to_string(),
+                        entities: Vec::new(),
+                        commit_id: Some(format!("commit-{}", c)),
+                        timestamp: 0,
+                        symbol_id: None,
+                        ast_node_type: None,
+                        file_path: None,
+                        segment_index: None,
+                        segment_count: None,
+                        ingested_at: None,
+                    })
+            })
+            .collect();
+        assert_eq!(turns.len(), 20);
+
+        let max_notes = args.max_notes.unwrap();
+        let dropped = cap_turns_to_max_notes(&mut turns, 2, max_notes);
+        assert_eq!(turns.len(), 5);
+        assert_eq!(dropped, 15);
+        assert!(2 + turns.len() <= max_notes);
+
+        // A graph already at the cap ingests nothing.
+        assert_eq!(cap_turns_to_max_notes(&mut turns, 9, max_notes), 5);
+        assert!(turns.is_empty());
+    }
+
     #[test]
     fn test_strip_html_comments() {
         let message = "Summary <!-- comment --> and more";
         let filters = GitFilterConfig {
             drop_patterns: vec![],
             html_comment_regex: Regex::new(r"(?s)<!--.*?-->").unwrap(),
+            merge_commit_policy: MergeCommitPolicy::Include,
         };
         let mut stats = GitFilterStats::default();
         let stripped = apply_git_line_filters(message, &filters, &mut stats).unwrap();
@@ -1483,6 +2395,7 @@ mod tests {
         let filters = GitFilterConfig {
             drop_patterns: vec![],
             html_comment_regex: Regex::new(r"(?s)<!--.*?-->").unwrap(),
+            merge_commit_policy: MergeCommitPolicy::Include,
         };
         let mut stats = GitFilterStats::default();
         let stripped = apply_git_line_filters(message, &filters, &mut stats).unwrap();
@@ -1490,4 +2403,237 @@ mod tests {
         assert!(stripped.contains("End"));
         assert!(!stripped.contains("multiline"));
     }
+
+    #[test]
+    fn test_parse_time_arg_accepts_epoch_and_rfc3339() {
+        let epoch = parse_time_arg("--time-start", "1714521600").unwrap();
+        let rfc = parse_time_arg("--time-start", "2024-05-01T00:00:00Z").unwrap();
+        assert_eq!(epoch, rfc);
+        assert_eq!(
+            parse_time_arg("--time-end", "2024-05-01T02:00:00+02:00").unwrap(),
+            rfc
+        );
+
+        // Both forms select the same timestamps from a window.
+        let timestamps = [1714521599_u64, 1714521600, 1714521601];
+        let kept_epoch: Vec<u64> = timestamps.iter().copied().filter(|t| *t >= epoch).collect();
+        let kept_rfc: Vec<u64> = timestamps.iter().copied().filter(|t| *t >= rfc).collect();
+        assert_eq!(kept_epoch, kept_rfc);
+
+        assert!(parse_time_arg("--temporal-now", "yesterday").is_err());
+        assert!(parse_time_arg("--temporal-now", "1969-12-31T00:00:00Z").is_err());
+    }
+
+    /// Create an empty repository in a fresh temporary directory.
+    #[cfg(feature = "git2-backend")]
+    fn temp_repo(prefix: &str) -> (PathBuf, git2::Repository) {
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        let dir = std::env::temp_dir().join(format!("{}_{}", prefix, stamp));
+        let repo = git2::Repository::init(&dir).unwrap();
+        (dir, repo)
+    }
+
+    /// Build a repo with `main: root -> work -> merge(work, side)` and return its path.
+    #[cfg(feature = "git2-backend")]
+    fn merge_fixture_repo() -> PathBuf {
+        use git2::{Signature, Time};
+
+        let (dir, repo) = temp_repo("spectral_cortex_merge_fixture");
+        let tree_id = repo.treebuilder(None).unwrap().write().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let commit = |message: &str, seconds: i64, parents: &[&git2::Commit]| {
+            let sig = Signature::new("tester", "tester@example.com", &Time::new(seconds, 0)).unwrap();
+            let oid = repo.commit(None, &sig, &sig, message, &tree, parents).unwrap();
+            repo.find_commit(oid).unwrap()
+        };
+
+        let root = commit("Add tokenizer", 1_700_000_000, &[]);
+        let side = commit("Add lexer error recovery", 1_700_000_100, &[&root]);
+        let work = commit("Add parser retries", 1_700_000_200, &[&root]);
+        let merge = commit(
+            "Merge branch 'lexer'\n\nConflicts:\n\tsrc/lexer.rs",
+            1_700_000_300,
+            &[&work, &side],
+        );
+        repo.reference("refs/heads/main", merge.id(), true, "fixture").unwrap();
+        repo.set_head("refs/heads/main").unwrap();
+        dir
+    }
+
+    #[cfg(feature = "git2-backend")]
+    #[test]
+    fn test_merge_commit_policy_is_applied() {
+        let repo = merge_fixture_repo();
+        let collect = |policy: &str| {
+            let cli = Cli::try_parse_from([
+                "spectral-cortex",
+                "ingest",
+                "--git-commit-split-mode",
+                "off",
+                "--merge-commit-policy",
+                policy,
+            ])
+            .unwrap();
+            let Commands::Ingest(args) = cli.command else {
+                panic!("expected ingest command");
+            };
+            let filters = GitFilterConfig::from_ingest_args(&args).unwrap();
+            let split_config = CommitSplitConfig::from_ingest_args(&args).unwrap();
+            let registry = crate::ast::registry::ParserRegistry::new();
+            collect_commits(&repo, None, &filters, &split_config, &registry).unwrap()
+        };
+        let merge_turn = |out: &CollectCommitsOutput| {
+            out.turns
+                .iter()
+                .find(|t| t.content.starts_with("Merge branch"))
+                .map(|t| t.content.clone())
+        };
+
+        let include = collect("include");
+        assert_eq!(include.turns.len(), 4);
+        assert!(merge_turn(&include).unwrap().contains("Conflicts:"));
+
+        let skip = collect("skip");
+        assert_eq!(skip.turns.len(), 3);
+        assert!(merge_turn(&skip).is_none());
+        assert_eq!(skip.filter_stats.merge_commits_skipped, 1);
+
+        let subject_only = collect("subject-only");
+        assert_eq!(subject_only.turns.len(), 4);
+        assert_eq!(merge_turn(&subject_only).unwrap().trim(), "Merge branch 'lexer'");
+        assert_eq!(subject_only.filter_stats.merge_commits_subject_only, 1);
+
+        assert!(MergeCommitPolicy::parse("squash").is_err());
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+
+    #[test]
+    fn test_split_segments_carry_index_and_count() {
+        let cli = Cli::try_parse_from(["spectral-cortex", "ingest", "--git-commit-split-mode", "strict"])
+            .unwrap();
+        let Commands::Ingest(args) = cli.command else {
+            panic!("expected ingest command");
+        };
+        let split_config = CommitSplitConfig::from_ingest_args(&args).unwrap();
+        let mut stats = CommitSplitStats::default();
+        let segments = split_commit_message(
+            "feat: add lexer\nfix: parser crash\ndocs: describe grammar",
+            &split_config,
+            &mut stats,
+        );
+        assert_eq!(segments.len(), 3);
+
+        let turns = segment_turns(segments, "tester", "abc123", 1_700_000_000);
+        embed::init(1, 0).unwrap();
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for (i, turn) in turns.iter().enumerate() {
+            let mut turn = turn.clone();
+            turn.turn_id = i as u64 + 1;
+            smg.ingest_turn(&turn).unwrap();
+        }
+
+        let mut notes: Vec<&spectral_cortex::SMGNote> = smg.notes.values().collect();
+        notes.sort_by_key(|n| n.note_id);
+        assert_eq!(notes.len(), 3);
+        for (i, note) in notes.iter().enumerate() {
+            assert_eq!(note.segment_index, Some(i));
+            assert_eq!(note.segment_count, Some(3));
+        }
+        assert!(notes[1].raw_content.starts_with("fix: parser crash"));
+    }
+
+    #[cfg(feature = "git2-backend")]
+    #[test]
+    fn test_reingest_commit_applies_new_split_mode() {
+        use git2::{Signature, Time};
+
+        let (dir, repo) = temp_repo("spectral_cortex_reingest_fixture");
+        let tree_id = repo.treebuilder(None).unwrap().write().unwrap();
+        let tree = repo.find_tree(tree_id).unwrap();
+        let sig = Signature::new("tester", "tester@example.com", &Time::new(1_700_000_000, 0)).unwrap();
+        let first = repo
+            .commit(Some("HEAD"), &sig, &sig, "chore: initial import", &tree, &[])
+            .unwrap();
+        let parent = repo.find_commit(first).unwrap();
+        let target = repo
+            .commit(
+                Some("HEAD"),
+                &sig,
+                &sig,
+                "feat: add lexer\nfix: parser crash\ndocs: describe grammar",
+                &tree,
+                &[&parent],
+            )
+            .unwrap()
+            .to_string();
+
+        let configs = |mode: &str| {
+            let cli = Cli::try_parse_from(["spectral-cortex", "ingest", "--git-commit-split-mode", mode])
+                .unwrap();
+            let Commands::Ingest(args) = cli.command else {
+                panic!("expected ingest command");
+            };
+            (
+                GitFilterConfig::from_ingest_args(&args).unwrap(),
+                CommitSplitConfig::from_ingest_args(&args).unwrap(),
+            )
+        };
+        let registry = crate::ast::registry::ParserRegistry::new();
+
+        embed::init(1, 0).unwrap();
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let (filters, split_off) = configs("off");
+        let collected = collect_commits(&dir, None, &filters, &split_off, &registry).unwrap();
+        for turn in &collected.turns {
+            smg.ingest_turn(turn).unwrap();
+        }
+        assert_eq!(smg.notes_for_commit(&target).len(), 1);
+
+        let (filters, split_strict) = configs("strict");
+        let short = &target[..10];
+        let (commit_id, turns) =
+            collect_commit_turns(&dir, short, &filters, &split_strict, &registry).unwrap();
+        assert_eq!(commit_id, target);
+        let (previous, new_ids) = smg.reingest_commit(&commit_id, &turns).unwrap();
+        assert_eq!((previous, new_ids.len()), (1, 3));
+        assert_eq!(smg.notes_for_commit(&target), new_ids);
+        assert_eq!(smg.notes.len(), 4);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_embed_output_has_model_dim_and_norm() {
+        let cli = Cli::try_parse_from(["spectral-cortex", "embed", "--text", "parser crash"]).unwrap();
+        let Commands::Embed(args) = cli.command else {
+            panic!("expected embed command");
+        };
+        assert_eq!(args.text.as_deref(), Some("parser crash"));
+        assert!(Cli::try_parse_from(["spectral-cortex", "embed"]).is_err());
+
+        embed::init(1, 0).unwrap();
+        let embedding = embed::get_embedding("parser crash").unwrap();
+        let value = embedding_json(&embedding);
+        let printed: Vec<f32> = value["embedding"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_f64().unwrap() as f32)
+            .collect();
+        assert_eq!(printed.len(), embed::EMBEDDING_DIM);
+        assert_eq!(value["dim"], embed::EMBEDDING_DIM);
+        let norm = printed.iter().map(|x| x * x).sum::<f32>().sqrt();
+        assert!((value["norm"].as_f64().unwrap() as f32 - norm).abs() < 1e-4);
+
+        let npy = npy_bytes(&embedding);
+        assert!(npy.starts_with(b"\x93NUMPY\x01\x00"));
+        let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        assert_eq!((10 + header_len) % 64, 0);
+        assert_eq!(npy.len(), 10 + header_len + 4 * embed::EMBEDDING_DIM);
+        let first = f32::from_le_bytes(npy[10 + header_len..14 + header_len].try_into().unwrap());
+        assert_eq!(first, embedding[0]);
+    }
 }
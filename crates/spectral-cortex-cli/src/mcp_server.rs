@@ -377,13 +377,16 @@ impl SpectralCortexMcpServer {
     }
 }
 
-pub fn run_mcp_server(smg_path: &Path) -> Result<()> {
+pub fn run_mcp_server(smg_path: &Path, query_cache: Option<usize>) -> Result<()> {
     let smg_path = smg_path
         .to_path_buf()
         .canonicalize()
         .unwrap_or_else(|_| smg_path.to_path_buf());
-    let smg = load_smg_json(&smg_path)
+    let mut smg = load_smg_json(&smg_path)
         .with_context(|| format!("failed to load SMG '{}'", smg_path.display()))?;
+    if let Some(capacity) = query_cache {
+        smg.enable_query_cache(capacity);
+    }
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -0,0 +1,239 @@
+//! Typed result objects for `query --json`.
+//!
+//! Both the JSON and human query output paths resolve turns through
+//! [`query_result_for_turn`], so field names and lookups live in one place.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use spectral_cortex::SpectralMemoryGraph;
+
+/// A note related to a query result via spectral similarity.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct RelatedNoteJson {
+    pub(crate) note_id: u32,
+    pub(crate) spectral_similarity: f32,
+}
+
+/// One retrieved turn resolved to the note that contains it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct QueryResultJson {
+    pub(crate) turn_id: u64,
+    pub(crate) note_id: u32,
+    pub(crate) score: f32,
+    pub(crate) commit_id: Option<String>,
+    pub(crate) symbol_id: Option<String>,
+    pub(crate) ast_node_type: Option<String>,
+    pub(crate) file_path: Option<String>,
+    pub(crate) raw_content: String,
+    pub(crate) context: String,
+    pub(crate) source_turn_ids: Vec<u64>,
+    pub(crate) related_notes: Vec<RelatedNoteJson>,
+    /// Omitted when the SMG has no cluster labels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) cluster_label: Option<usize>,
+    /// Further hits from the same commit; set only on the first hit of each commit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) contextual_hits: Option<Vec<QueryResultJson>>,
+}
+
+/// A turn id with no note containing it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct UnresolvedTurnJson {
+    pub(crate) turn_id: u64,
+    pub(crate) score: f32,
+}
+
+/// An entry of the `results` array.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub(crate) enum QueryResultEntry {
+    Note(Box<QueryResultJson>),
+    Unresolved(UnresolvedTurnJson),
+}
+
+/// Map each turn id to the lowest-id note containing it, as `(sorted index, note_id)`.
+///
+/// The index is the note's position in ascending note-id order, which is how cluster
+/// labels are indexed. Build it once per query and share it across results.
+pub(crate) fn turn_note_index(smg: &SpectralMemoryGraph) -> HashMap<u64, (usize, u32)> {
+    let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
+    note_ids.sort_unstable();
+    let mut index = HashMap::new();
+    for (idx, nid) in note_ids.iter().enumerate() {
+        for tid in &smg.notes[nid].source_turn_ids {
+            index.entry(*tid).or_insert((idx, *nid));
+        }
+    }
+    index
+}
+
+/// Resolve `turn_id` to the lowest-id note containing it via `turn_index`
+/// (from [`turn_note_index`]).
+///
+/// `related_k` limits the related notes attached to the result.
+pub(crate) fn query_result_for_turn(
+    smg: &SpectralMemoryGraph,
+    turn_index: &HashMap<u64, (usize, u32)>,
+    turn_id: u64,
+    score: f32,
+    related_k: Option<usize>,
+) -> QueryResultEntry {
+    let found = turn_index
+        .get(&turn_id)
+        .and_then(|&(idx, nid)| smg.notes.get(&nid).map(|note| (idx, nid, note)));
+    let Some((idx, note_id, note)) = found else {
+        return QueryResultEntry::Unresolved(UnresolvedTurnJson { turn_id, score });
+    };
+
+    let commit_id = note
+        .source_turn_ids
+        .iter()
+        .position(|x| *x == turn_id)
+        .and_then(|pos| note.source_commit_ids.get(pos).cloned().flatten());
+    let related_notes = smg
+        .get_related_note_links(note_id, related_k)
+        .into_iter()
+        .map(|(note_id, spectral_similarity)| RelatedNoteJson {
+            note_id,
+            spectral_similarity,
+        })
+        .collect();
+    // Cluster labels are indexed by sorted note id.
+    let cluster_label = smg
+        .cluster_labels
+        .as_ref()
+        .and_then(|labels| labels.get(idx).copied());
+
+    QueryResultEntry::Note(Box::new(QueryResultJson {
+        turn_id,
+        note_id,
+        score,
+        commit_id,
+        symbol_id: note.symbol_id.clone(),
+        ast_node_type: note.ast_node_type.clone(),
+        file_path: note.file_path.clone(),
+        raw_content: note.raw_content.clone(),
+        context: note.context(),
+        source_turn_ids: note.source_turn_ids.clone(),
+        related_notes,
+        cluster_label,
+        contextual_hits: None,
+    }))
+}
+
+/// Build the `results` array: hits are grouped under the first hit of their commit,
+/// followed by hits without a commit and unresolved turns, each in score order.
+pub(crate) fn build_query_results(
+    smg: &SpectralMemoryGraph,
+    results: &[(u64, f32)],
+    related_k: Option<usize>,
+) -> Vec<QueryResultEntry> {
+    let mut primary: Vec<QueryResultJson> = Vec::new();
+    let mut primary_by_commit: HashMap<String, usize> = HashMap::new();
+    let mut fallback: Vec<QueryResultEntry> = Vec::new();
+
+    let turn_index = turn_note_index(smg);
+    for (tid, score) in results {
+        match query_result_for_turn(smg, &turn_index, *tid, *score, related_k) {
+            QueryResultEntry::Note(mut hit) => match hit.commit_id.clone() {
+                Some(cid) => match primary_by_commit.get(&cid) {
+                    Some(&pos) => primary[pos]
+                        .contextual_hits
+                        .get_or_insert_with(Vec::new)
+                        .push(*hit),
+                    None => {
+                        hit.contextual_hits = Some(Vec::new());
+                        primary_by_commit.insert(cid, primary.len());
+                        primary.push(*hit);
+                    }
+                },
+                None => fallback.push(QueryResultEntry::Note(hit)),
+            },
+            unresolved => fallback.push(unresolved),
+        }
+    }
+
+    primary
+        .into_iter()
+        .map(|hit| QueryResultEntry::Note(Box::new(hit)))
+        .chain(fallback)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_query_results;
+    use serde_json::json;
+    use spectral_cortex::{SMGNote, SpectralMemoryGraph};
+
+    fn note(note_id: u32, content: &str, turns: Vec<u64>, commit: Option<&str>) -> SMGNote {
+        SMGNote {
+            source_commit_ids: vec![commit.map(str::to_string); turns.len()],
+            source_timestamps: vec![0; turns.len()],
+            source_turn_ids: turns,
+            file_path: Some("src/lib.rs".to_string()),
+            ..SMGNote::new(note_id, content, vec![1.0, 0.0])
+        }
+    }
+
+    #[test]
+    fn test_query_results_serialize_to_expected_shape() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        smg.notes.insert(0, note(0, "fix parser", vec![10], Some("abc")));
+        smg.notes.insert(1, note(1, "parser tests", vec![11], Some("abc")));
+        smg.notes.insert(2, note(2, "loose note", vec![12], None));
+        smg.cluster_labels = Some(vec![3, 3, 1].into());
+
+        let results = build_query_results(&smg, &[(10, 0.9), (12, 0.7), (11, 0.5), (99, 0.1)], Some(5));
+        let value = serde_json::to_value(&results).unwrap();
+        assert_eq!(
+            value,
+            json!([
+                {
+                    "turn_id": 10,
+                    "note_id": 0,
+                    "score": 0.9f32,
+                    "commit_id": "abc",
+                    "symbol_id": null,
+                    "ast_node_type": null,
+                    "file_path": "src/lib.rs",
+                    "raw_content": "fix parser",
+                    "context": "fix parser",
+                    "source_turn_ids": [10],
+                    "related_notes": [],
+                    "cluster_label": 3,
+                    "contextual_hits": [{
+                        "turn_id": 11,
+                        "note_id": 1,
+                        "score": 0.5f32,
+                        "commit_id": "abc",
+                        "symbol_id": null,
+                        "ast_node_type": null,
+                        "file_path": "src/lib.rs",
+                        "raw_content": "parser tests",
+                        "context": "parser tests",
+                        "source_turn_ids": [11],
+                        "related_notes": [],
+                        "cluster_label": 3
+                    }]
+                },
+                {
+                    "turn_id": 12,
+                    "note_id": 2,
+                    "score": 0.7f32,
+                    "commit_id": null,
+                    "symbol_id": null,
+                    "ast_node_type": null,
+                    "file_path": "src/lib.rs",
+                    "raw_content": "loose note",
+                    "context": "loose note",
+                    "source_turn_ids": [12],
+                    "related_notes": [],
+                    "cluster_label": 1
+                },
+                { "turn_id": 99, "score": 0.1f32 }
+            ])
+        );
+    }
+}
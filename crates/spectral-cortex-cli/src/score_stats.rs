@@ -0,0 +1,167 @@
+//! Score distribution over a batch of queries, for tuning `--min-score`.
+
+use anyhow::Result;
+use serde::Serialize;
+use spectral_cortex::SpectralMemoryGraph;
+
+/// Percentiles and a histogram over `[0, 1]` for a set of final scores.
+///
+/// Percentiles are computed on the raw scores; only histogram binning clamps them into
+/// `[0, 1]`, so out-of-range scores land in the first or last bin.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct ScoreSummary {
+    pub(crate) count: usize,
+    pub(crate) min: f32,
+    pub(crate) p10: f32,
+    pub(crate) p50: f32,
+    pub(crate) p90: f32,
+    pub(crate) max: f32,
+    /// Counts per equal-width bin over `[0, 1]`; a score of exactly 1.0 falls in the last bin.
+    /// Empty when no histogram was requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) histogram: Vec<usize>,
+}
+
+/// Final scores gathered by running each query.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct QueryScores {
+    /// Best score per query that returned any result.
+    pub(crate) top1: Vec<f32>,
+    /// Every returned score (up to `top_k` per query).
+    pub(crate) topk: Vec<f32>,
+    /// Queries that returned no results.
+    pub(crate) empty_queries: usize,
+}
+
+/// Run every query with default temporal re-ranking and collect its final scores.
+pub(crate) fn collect_query_scores(
+    smg: &SpectralMemoryGraph,
+    queries: &[String],
+    top_k: usize,
+) -> Result<QueryScores> {
+    let mut scores = QueryScores::default();
+    for query in queries {
+        let results = smg.retrieve_with_scores(query, top_k)?;
+        let mut finals: Vec<f32> = results.into_iter().take(top_k).map(|(_, s)| s).collect();
+        finals.sort_by(|a, b| b.total_cmp(a));
+        match finals.first() {
+            Some(best) => scores.top1.push(*best),
+            None => scores.empty_queries += 1,
+        }
+        scores.topk.extend(finals);
+    }
+    Ok(scores)
+}
+
+/// Linearly interpolated percentile (`p` in `[0, 100]`) of ascending `sorted` scores.
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (p / 100.0) * (sorted.len() - 1) as f32;
+    let lo = rank.floor() as usize;
+    let hi = rank.ceil() as usize;
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f32)
+}
+
+/// Summarize `scores` with `bins` histogram bins (`0` skips the histogram).
+pub(crate) fn summarize_scores(scores: &[f32], bins: usize) -> ScoreSummary {
+    let mut sorted: Vec<f32> = scores.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let mut histogram = vec![0usize; bins];
+    if bins > 0 {
+        for s in &sorted {
+            let bin = ((s.clamp(0.0, 1.0) * bins as f32) as usize).min(bins - 1);
+            histogram[bin] += 1;
+        }
+    }
+    ScoreSummary {
+        count: sorted.len(),
+        min: sorted.first().copied().unwrap_or(0.0),
+        p10: percentile(&sorted, 10.0),
+        p50: percentile(&sorted, 50.0),
+        p90: percentile(&sorted, 90.0),
+        max: sorted.last().copied().unwrap_or(0.0),
+        histogram,
+    }
+}
+
+/// Human-readable summary with one `#` bar per bin, scaled to `width` characters.
+pub(crate) fn format_summary(label: &str, summary: &ScoreSummary, width: usize) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{}: n={} min={:.3} p10={:.3} p50={:.3} p90={:.3} max={:.3}",
+        label, summary.count, summary.min, summary.p10, summary.p50, summary.p90, summary.max
+    )];
+    let bins = summary.histogram.len();
+    let peak = summary.histogram.iter().copied().max().unwrap_or(0).max(1);
+    for (i, count) in summary.histogram.iter().enumerate() {
+        let lo = i as f32 / bins as f32;
+        let hi = (i + 1) as f32 / bins as f32;
+        let bar = "#".repeat(count * width / peak);
+        let close = if i + 1 == bins { "]" } else { ")" };
+        lines.push(format!("  [{:.2}, {:.2}{} {:>5} {}", lo, hi, close, count, bar));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_query_scores, summarize_scores};
+    use spectral_cortex::{embed, ConversationTurn, SpectralMemoryGraph};
+
+    #[test]
+    fn test_query_batch_percentiles_are_monotonic_and_bounded() {
+        embed::init(1, 0).unwrap();
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let contents = [
+            "fix parser crash on empty input",
+            "add lexer support for raw strings",
+            "refactor query cache eviction",
+            "document spectral build tuning",
+            "speed up embedding batch ingest",
+            "handle merge commits during update",
+        ];
+        for (i, content) in contents.iter().enumerate() {
+            smg.ingest_turn(&ConversationTurn {
+                turn_id: i as u64,
+                speaker: "git".to_string(),
+                content: content.to_string(),
+                topic: "git".to_string(),
+                entities: vec![],
+                commit_id: Some(format!("c{}", i)),
+                timestamp: 1_700_000_000 + i as u64,
+                symbol_id: None,
+                ast_node_type: None,
+                file_path: None,
+                segment_index: None,
+                segment_count: None,
+                ingested_at: None,
+            })
+            .unwrap();
+        }
+        let queries: Vec<String> = ["parser crash", "query cache", "merge commits", "embedding"]
+            .iter()
+            .map(|q| q.to_string())
+            .collect();
+
+        let scores = collect_query_scores(&smg, &queries, 3).unwrap();
+        assert!(!scores.top1.is_empty());
+        assert_eq!(scores.top1.len() + scores.empty_queries, queries.len());
+        for values in [&scores.top1, &scores.topk] {
+            let summary = summarize_scores(values, 10);
+            assert_eq!(summary.count, values.len());
+            assert_eq!(summary.histogram.iter().sum::<usize>(), summary.count);
+            let ordered = [summary.min, summary.p10, summary.p50, summary.p90, summary.max];
+            assert!(ordered.windows(2).all(|w| w[0] <= w[1]), "not monotonic: {:?}", ordered);
+            assert!(ordered.iter().all(|v| (0.0..=1.0).contains(v)), "out of range: {:?}", ordered);
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_scores_keep_raw_percentiles_and_clamp_bins() {
+        let summary = summarize_scores(&[-0.5, 0.25, 1.5], 4);
+        assert_eq!((summary.min, summary.p50, summary.max), (-0.5, 0.25, 1.5));
+        assert_eq!(summary.histogram, vec![1, 1, 0, 1]);
+    }
+}
@@ -40,6 +40,9 @@ fn integration_candidate_k_min_score() -> Result<()> {
             symbol_id: None,
             ast_node_type: None,
             file_path: None,
+            segment_index: None,
+            segment_count: None,
+            ingested_at: None,
         };
         turns.push(t);
     }
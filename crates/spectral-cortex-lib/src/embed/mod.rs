@@ -164,14 +164,37 @@ mod fake {
     use once_cell::sync::Lazy;
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
+    use std::sync::atomic::{AtomicU64, Ordering};
     use std::sync::Mutex;
 
-    // Keep the fake embedding dimension compatible with common MiniLM dims (384).
+    // Keep the fake embedding dimension compatible with the MiniLM dims.
     // This keeps downstream code shapes stable for development and tests.
-    const FAKE_EMBED_DIM: usize = 384;
+    const FAKE_EMBED_DIM: usize = super::EMBEDDING_DIM;
 
-    // Simple mutex to mirror the initialization semantics of the real embedder.
-    static FAKE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+    // Simple mutex to mirror the initialization semantics of the real embedder. It also
+    // guards the (seed, magnitude) of the optional jitter, which is process-wide so that
+    // embeddings computed on worker threads (batch ingest, rayon) are perturbed too.
+    static FAKE_LOCK: Lazy<Mutex<(u64, f32)>> = Lazy::new(|| Mutex::new((0, 0.0)));
+
+    // Number of texts embedded so far, on any thread, for tests asserting cache behaviour.
+    static FAKE_CALLS: AtomicU64 = AtomicU64::new(0);
+
+    /// Add seeded noise of up to `magnitude` per component to fake embeddings.
+    ///
+    /// The noise for a text depends only on `(seed, text)`, never on batch position or
+    /// worker, so perturbed runs are reproducible. A magnitude of `0.0` (the default)
+    /// restores exact deterministic embeddings. The setting is process-wide and applies
+    /// to every subsequent embedding, on any thread; it bumps the embedder generation so
+    /// cached embeddings computed under the previous setting are dropped.
+    pub fn set_fake_jitter(seed: u64, magnitude: f32) {
+        *FAKE_LOCK.lock().unwrap() = (seed, magnitude);
+        super::bump_generation();
+    }
+
+    /// Number of texts embedded so far, on any thread.
+    pub fn fake_embed_calls() -> u64 {
+        FAKE_CALLS.load(Ordering::SeqCst)
+    }
 
     /// Initialise the fake embedder (no-op but mirrors the real init API).
     pub fn init(_workers: usize, _cache_size: usize) -> Result<()> {
@@ -183,7 +206,8 @@ mod fake {
     /// Deterministic, fast embedding: hash the input together with the index to
     /// produce stable floats in [-1.0, 1.0]. This is sufficient for development,
     /// testing, and CI where real model assets are unnecessary.
-    fn deterministic_embedding(text: &str) -> Vec<f32> {
+    fn deterministic_embedding(text: &str, (seed, magnitude): (u64, f32)) -> Vec<f32> {
+        FAKE_CALLS.fetch_add(1, Ordering::SeqCst);
         let mut out = Vec::with_capacity(FAKE_EMBED_DIM);
         for i in 0..FAKE_EMBED_DIM {
             let mut hasher = DefaultHasher::new();
@@ -196,13 +220,24 @@ mod fake {
             let v = (v * 2.0) - 1.0;
             out.push(v as f32);
         }
+
+        if magnitude != 0.0 {
+            for (i, x) in out.iter_mut().enumerate() {
+                let mut hasher = DefaultHasher::new();
+                seed.hash(&mut hasher);
+                text.hash(&mut hasher);
+                i.hash(&mut hasher);
+                let noise = (hasher.finish() as f64) / (u64::MAX as f64) * 2.0 - 1.0;
+                *x += magnitude * noise as f32;
+            }
+        }
         out
     }
 
     /// Embed a single string deterministically.
     pub fn get_embedding(text: &str) -> Result<Vec<f32>> {
-        let _g = FAKE_LOCK.lock().unwrap();
-        Ok(deterministic_embedding(text))
+        let jitter = *FAKE_LOCK.lock().unwrap();
+        Ok(deterministic_embedding(text, jitter))
     }
 
     /// Embed a batch of texts using the same deterministic function with progress reporting.
@@ -210,12 +245,12 @@ mod fake {
         texts: &[String],
         progress: Option<crate::graph::ProgressCallback>,
     ) -> Result<Vec<Vec<f32>>> {
-        let _g = FAKE_LOCK.lock().unwrap();
+        let jitter = *FAKE_LOCK.lock().unwrap();
         let mut res = Vec::with_capacity(texts.len());
         let total = texts.len();
 
         for (idx, t) in texts.iter().enumerate() {
-            res.push(deterministic_embedding(t));
+            res.push(deterministic_embedding(t, jitter));
 
             // Update progress
             if let Some(ref cb) = progress {
@@ -239,7 +274,41 @@ mod fake {
 // - Otherwise the real MiniLM embedder is used by default (no feature flag
 //   required).
 #[cfg(any(test, feature = "fake-embed"))]
-pub use fake::{get_embedding, get_embeddings, init, shutdown};
+pub use fake::{fake_embed_calls, get_embedding, get_embeddings, set_fake_jitter};
+
+#[cfg(not(any(test, feature = "fake-embed")))]
+pub use real::{get_embedding, get_embeddings};
 
+#[cfg(any(test, feature = "fake-embed"))]
+use fake as backend;
 #[cfg(not(any(test, feature = "fake-embed")))]
-pub use real::{get_embedding, get_embeddings, init, shutdown};
+use real as backend;
+
+/// Output dimension of the MiniLM embedder; the fake embedder produces the same.
+pub const EMBEDDING_DIM: usize = 384;
+
+/// Bumped whenever the embedder is (re)initialised or shut down, or its output changes
+/// (fake jitter), so caches of embeddings computed earlier can detect they are stale.
+static GENERATION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+fn bump_generation() {
+    GENERATION.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Initialise the embedder; see the backend `init` for the arguments.
+pub fn init(workers: usize, cache_size: usize) -> anyhow::Result<()> {
+    backend::init(workers, cache_size)?;
+    bump_generation();
+    Ok(())
+}
+
+/// Shut the embedder down.
+pub fn shutdown() -> anyhow::Result<()> {
+    bump_generation();
+    backend::shutdown()
+}
+
+/// Current embedder generation (see `init`).
+pub fn generation() -> u64 {
+    GENERATION.load(std::sync::atomic::Ordering::SeqCst)
+}
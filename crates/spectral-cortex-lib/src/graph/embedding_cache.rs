@@ -0,0 +1,103 @@
+//! Process-wide LRU cache of query embeddings.
+//!
+//! Retrieval embeds the query text on every call; repeated identical queries (pagination,
+//! MMR re-selection, polarity filtering) reuse the cached vector instead. Entries are
+//! tied to the embedder generation (`embed::generation`) and dropped wholesale when the
+//! embedder is re-initialised or its output changes (e.g. fake embedder jitter).
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+
+use crate::embed;
+
+/// Maximum number of distinct query texts kept.
+pub const QUERY_EMBEDDING_CACHE_CAPACITY: usize = 256;
+
+static CACHE: Lazy<Mutex<QueryEmbeddingCache>> =
+    Lazy::new(|| Mutex::new(QueryEmbeddingCache::new(QUERY_EMBEDDING_CACHE_CAPACITY)));
+
+/// Lock the cache, recovering from a poisoned lock (entries are only ever whole vectors).
+fn lock_cache() -> MutexGuard<'static, QueryEmbeddingCache> {
+    CACHE.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// LRU map from query text to its embedding.
+struct QueryEmbeddingCache {
+    capacity: usize,
+    generation: u64,
+    entries: HashMap<String, Vec<f32>>,
+    /// Keys from least to most recently used.
+    order: VecDeque<String>,
+}
+
+impl QueryEmbeddingCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            generation: embed::generation(),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Drop everything if the embedder changed since the entries were computed.
+    fn sync_generation(&mut self) {
+        let current = embed::generation();
+        if current != self.generation {
+            self.clear();
+            self.generation = current;
+        }
+    }
+
+    fn get(&mut self, text: &str) -> Option<Vec<f32>> {
+        self.sync_generation();
+        let emb = self.entries.get(text)?.clone();
+        if let Some(pos) = self.order.iter().position(|k| k == text) {
+            let key = self.order.remove(pos).expect("position is in bounds");
+            self.order.push_back(key);
+        }
+        Some(emb)
+    }
+
+    fn insert(&mut self, text: &str, emb: Vec<f32>, generation: u64) {
+        self.sync_generation();
+        // Computed by an embedder that has since been replaced.
+        if generation != self.generation {
+            return;
+        }
+        if self.entries.insert(text.to_string(), emb).is_some() {
+            return;
+        }
+        self.order.push_back(text.to_string());
+        while self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Embed `query`, reusing a cached embedding for identical text when available.
+pub fn query_embedding(query: &str) -> Result<Vec<f32>> {
+    if let Some(emb) = lock_cache().get(query) {
+        return Ok(emb);
+    }
+    // Embed outside the lock so concurrent queries are not serialised on it.
+    let generation = embed::generation();
+    let emb = embed::get_embedding(query)?;
+    lock_cache().insert(query, emb.clone(), generation);
+    Ok(emb)
+}
+
+/// Drop all cached query embeddings.
+pub fn clear_query_embedding_cache() {
+    lock_cache().clear();
+}
@@ -24,9 +24,82 @@ use crate::utils::logging;
 /// The callback receives a message describing the current step and a progress fraction (0.0..1.0).
 pub type ProgressCallback = Arc<dyn Fn(String, f32) + Send + Sync>;
 
+/// Cosine similarity between two vectors with precomputed L2 norms (`0.0` if either is zero).
+fn vector_cosine(a: &[f32], norm_a: f32, b: &[f32], norm_b: f32) -> f32 {
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    dot / (norm_a * norm_b)
+}
+
+/// Cosine similarity between two notes using their stored embeddings and norms.
+fn cosine_similarity(a: &SMGNote, b: &SMGNote) -> f32 {
+    vector_cosine(&a.embedding, a.norm, &b.embedding, b.norm)
+}
+
+/// Deterministic ordering of long-range links: higher similarity first, then id order.
+fn link_order(a: &(u32, u32, f32), b: &(u32, u32, f32)) -> std::cmp::Ordering {
+    b.2.total_cmp(&a.2)
+        .then_with(|| a.0.cmp(&b.0))
+        .then_with(|| a.1.cmp(&b.1))
+}
+
+/// Maximum number of notes scored per batch by `retrieve_candidates_streaming`.
+pub const STREAM_BATCH_NOTES: usize = 256;
+
+/// Added to the per-cluster score bounds in `retrieve_candidates_streaming` so float
+/// rounding can only delay a note, never send it ahead of a higher score.
+const STREAM_BOUND_SLACK: f32 = 1e-4;
+
+/// Number of notes scored between deadline checks in `retrieve_candidates_until`.
+pub const DEADLINE_CHUNK_NOTES: usize = 512;
+
+/// Error returned when retrieval passes its deadline.
+///
+/// Retrieval functions return `anyhow::Error`; recover this with
+/// `err.downcast_ref::<RetrievalTimeout>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetrievalTimeout {
+    /// Notes scored before the deadline was detected.
+    pub notes_scored: usize,
+}
+
+impl std::fmt::Display for RetrievalTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "retrieval timed out after scoring {} notes", self.notes_scored)
+    }
+}
+
+impl std::error::Error for RetrievalTimeout {}
+
+fn deadline_passed(deadline: Option<Instant>) -> bool {
+    matches!(deadline, Some(d) if Instant::now() >= d)
+}
+
+/// Whether note `id` exists and carries one of `allowed` labels (`labels` indexed by sorted `note_ids`).
+fn labels_contain(labels: &Array1<usize>, note_ids: &[u32], id: u32, allowed: &[usize]) -> bool {
+    matches!(
+        note_ids.binary_search(&id).ok().and_then(|idx| labels.get(idx)),
+        Some(lbl) if allowed.contains(lbl)
+    )
+}
+
+/// Hash note content for exact-duplicate detection.
+fn content_hash(text: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
 /// Submodules
+pub mod embedding_cache;
+pub mod query_cache;
 pub mod spectral;
 
+use query_cache::{QueryCache, QueryCacheKey, QueryCacheStats};
+
 /// Spectral Memory Graph: in-memory notes + cached structures used for
 /// retrieval and clustering.
 ///
@@ -37,6 +110,8 @@ pub struct SpectralMemoryGraph {
     pub construction_time: Duration,
     // Cached structures for spectral processing
     pub similarity_matrix: Option<CsrMatrix<f32>>, // sparse similarity of embeddings
+    /// Note id of each `similarity_matrix` row, as of the build that produced it.
+    pub similarity_note_ids: Option<Vec<u32>>,
     pub spectral_embeddings: Option<Array2<f32>>, // eigenvectors (n x k)
     pub cluster_labels: Option<Array1<usize>>,  // optional K‑Means labels
     pub cluster_centroids: Option<HashMap<usize, Vec<f32>>>, // optional mean embeddings per cluster
@@ -44,8 +119,22 @@ pub struct SpectralMemoryGraph {
     pub long_range_links: Option<Vec<(u32, u32, f32)>>, // (note_id_a, note_id_b, spectral_similarity)
     /// The configuration used during the last spectral build.
     pub last_build_config: Option<SpectralBuildConfig>,
+    /// Cap on candidates a single note contributes during retrieval (its most recent
+    /// turns); `None` expands every source turn.
+    pub max_candidates_per_note: Option<usize>,
+    /// Whether time-filtered retrieval keeps notes without timestamps.
+    pub missing_timestamp_policy: MissingTimestampPolicy,
+    /// Opt-in retrieval result cache (see `enable_query_cache`).
+    query_cache: Option<std::sync::Mutex<QueryCache>>,
+    /// Bumped by every mutating method; tags query cache entries (see `mark_modified`).
+    generation: u64,
+    /// `cluster_radii` result and the generation it was computed at.
+    cluster_radii: std::sync::Mutex<Option<(u64, ClusterRadii)>>,
 }
 
+/// Angular radius per cluster label, shared between `cluster_radii` callers.
+type ClusterRadii = Arc<HashMap<usize, f32>>;
+
 /// Configurable parameters for spectral-structure construction.
 #[derive(Debug, Clone)]
 pub struct SpectralBuildConfig {
@@ -67,6 +156,14 @@ pub struct SpectralBuildConfig {
     pub structural_beta: f32,
     /// Spectral polarity threshold for pruning noise
     pub polarity_threshold: f32,
+    /// Maximum number of long-range links kept (strongest first); `None` keeps all.
+    pub max_links: Option<usize>,
+    /// Memory budget in bytes for the `n × d` embedding matrix; `None` disables the guard.
+    pub max_embedding_matrix_bytes: Option<usize>,
+    /// Maximum number of K-Means iterations.
+    pub kmeans_max_iters: u64,
+    /// K-Means convergence tolerance on centroid movement.
+    pub kmeans_tolerance: f32,
 }
 
 impl Default for SpectralBuildConfig {
@@ -81,6 +178,10 @@ impl Default for SpectralBuildConfig {
             structural_alpha: 0.8,
             structural_beta: 0.2,
             polarity_threshold: 0.85,
+            max_links: None,
+            max_embedding_matrix_bytes: None,
+            kmeans_max_iters: 100,
+            kmeans_tolerance: 1e-4,
         }
     }
 }
@@ -112,10 +213,36 @@ impl SpectralBuildConfig {
         if self.max_clusters < self.min_clusters {
             return Err(anyhow::anyhow!("max_clusters must be >= min_clusters"));
         }
+        if self.kmeans_max_iters == 0 {
+            return Err(anyhow::anyhow!("kmeans_max_iters must be >= 1"));
+        }
+        if self.kmeans_tolerance.is_nan() || self.kmeans_tolerance <= 0.0 {
+            return Err(anyhow::anyhow!("kmeans_tolerance must be > 0.0"));
+        }
         Ok(())
     }
 }
 
+/// Scoring used for the `related_note_links` fallback of `get_related_note_links`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RelatednessMetric {
+    /// Cosine similarity between the stored note embeddings.
+    #[default]
+    EmbeddingCosine,
+    /// The score persisted alongside each link (may be `0.0` for hand-edited links).
+    StoredScore,
+}
+
+/// Treatment of notes with no `source_timestamps` by time-filtered retrieval.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissingTimestampPolicy {
+    /// Undated notes never match a time filter.
+    #[default]
+    Exclude,
+    /// Undated notes pass every time filter.
+    Include,
+}
+
 impl SpectralMemoryGraph {
     /// Create a new, empty SMG.
     pub fn new() -> Result<Self> {
@@ -125,15 +252,75 @@ impl SpectralMemoryGraph {
             next_id: 0,
             construction_time: Duration::new(0, 0),
             similarity_matrix: None,
+            similarity_note_ids: None,
             spectral_embeddings: None,
             cluster_labels: None,
             cluster_centroids: None,
             cluster_centroid_norms: None,
             long_range_links: None,
             last_build_config: None,
+            max_candidates_per_note: None,
+            missing_timestamp_policy: MissingTimestampPolicy::default(),
+            query_cache: None,
+            generation: 0,
+            cluster_radii: std::sync::Mutex::new(None),
         })
     }
 
+    /// Enable an LRU cache of retrieval results holding up to `capacity` queries.
+    ///
+    /// Results from `retrieve_with_scores_config` (and the helpers built on it) are cached
+    /// by query text and effective retrieval/temporal configuration. Each entry is tagged
+    /// with `generation()`, which every mutating method bumps, so mutating the graph
+    /// invalidates it; call `mark_modified` after editing the public fields directly.
+    /// Note that a temporal config without `now_seconds` is keyed as-is, so cached scores
+    /// keep the recency computed at insertion time.
+    pub fn enable_query_cache(&mut self, capacity: usize) {
+        self.query_cache = Some(std::sync::Mutex::new(QueryCache::new(capacity)));
+    }
+
+    /// Disable and drop the retrieval result cache.
+    pub fn disable_query_cache(&mut self) {
+        self.query_cache = None;
+    }
+
+    /// Hit/miss counters of the retrieval result cache, if enabled.
+    pub fn query_cache_stats(&self) -> Option<QueryCacheStats> {
+        self.lock_query_cache().map(|cache| cache.stats())
+    }
+
+    /// Lock the query cache, recovering from a poisoned lock (the cache holds no
+    /// invariants a panicking reader could break).
+    fn lock_query_cache(&self) -> Option<std::sync::MutexGuard<'_, QueryCache>> {
+        self.query_cache
+            .as_ref()
+            .map(|cache| cache.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+
+    /// Counter of mutations, bumped by every `&mut self` method that changes notes or
+    /// spectral structures. Cheap to read; used to validate query cache entries.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Record that the graph changed. Call this after editing the public fields directly
+    /// so cached query results computed before the edit are not served.
+    pub fn mark_modified(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    /// Number of notes without source timestamps.
+    ///
+    /// Under `MissingTimestampPolicy::Exclude` these notes never match a time filter.
+    /// Time-filtered retrieval does not report them; check this once (e.g. after loading)
+    /// to decide whether to warn or switch the policy.
+    pub fn undated_note_count(&self) -> usize {
+        self.notes
+            .values()
+            .filter(|note| note.source_timestamps.is_empty())
+            .count()
+    }
+
     /// Get long-range links with optional top-k limit.
     ///
     /// Returns pairs of (note_id_a, note_id_b, spectral_similarity) for notes that are
@@ -142,12 +329,7 @@ impl SpectralMemoryGraph {
         match &self.long_range_links {
             Some(links) => {
                 let mut links = links.clone();
-                // Deterministic ordering: higher similarity first, then id order.
-                links.sort_by(|a, b| {
-                    b.2.total_cmp(&a.2)
-                        .then_with(|| a.0.cmp(&b.0))
-                        .then_with(|| a.1.cmp(&b.1))
-                });
+                links.sort_by(link_order);
                 if let Some(k) = top_k {
                     links.truncate(k);
                 }
@@ -157,13 +339,131 @@ impl SpectralMemoryGraph {
         }
     }
 
+    /// Sort `long_range_links` into the deterministic order served by the link getters.
+    ///
+    /// Building and loading keep the links sorted; call this after assigning the field
+    /// directly, before paging with `get_long_range_links_page`.
+    pub fn sort_long_range_links(&mut self) {
+        if let Some(links) = &mut self.long_range_links {
+            links.sort_by(link_order);
+        }
+    }
+
+    /// Get one page of long-range links in the same order as `get_long_range_links`.
+    ///
+    /// `cursor` is the offset of the first link to return (start with `0`). Only the
+    /// requested page is copied: pages are slices of the stored list, which building and
+    /// loading keep sorted (see `sort_long_range_links`).
+    ///
+    /// # Returns
+    ///
+    /// Up to `limit` links and the cursor of the next page, or `None` when this page
+    /// reaches the end.
+    pub fn get_long_range_links_page(
+        &self,
+        cursor: usize,
+        limit: usize,
+    ) -> (Vec<(u32, u32, f32)>, Option<usize>) {
+        let Some(links) = &self.long_range_links else {
+            return (Vec::new(), None);
+        };
+        let end = cursor.saturating_add(limit).min(links.len());
+        let page = links.get(cursor..end).unwrap_or(&[]).to_vec();
+        let next = (end < links.len() && limit > 0).then_some(end);
+        (page, next)
+    }
+
+    /// Find the cheapest path from note `a` to note `b` through the long-range link graph.
+    ///
+    /// Links are treated as undirected edges with cost `1 - similarity` (clamped at zero),
+    /// so paths prefer strong links. Ties are broken by note id for determinism.
+    ///
+    /// # Returns
+    ///
+    /// The note ids from `a` to `b` inclusive (`[a]` when `a == b`), or `None` if either
+    /// note is missing or the two are not connected.
+    pub fn link_path(&self, a: u32, b: u32) -> Option<Vec<u32>> {
+        use std::cmp::{Ordering, Reverse};
+        use std::collections::BinaryHeap;
+
+        /// Heap entry ordered by cost, then note id.
+        #[derive(PartialEq)]
+        struct Entry(f32, u32);
+        impl Eq for Entry {}
+        impl PartialOrd for Entry {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Entry {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0).then_with(|| self.1.cmp(&other.1))
+            }
+        }
+
+        if !self.notes.contains_key(&a) || !self.notes.contains_key(&b) {
+            return None;
+        }
+        if a == b {
+            return Some(vec![a]);
+        }
+
+        let mut adjacency: HashMap<u32, Vec<(u32, f32)>> = HashMap::new();
+        for (x, y, sim) in self.long_range_links.iter().flatten() {
+            let cost = (1.0 - sim).max(0.0);
+            adjacency.entry(*x).or_default().push((*y, cost));
+            adjacency.entry(*y).or_default().push((*x, cost));
+        }
+
+        let mut dist: HashMap<u32, f32> = HashMap::from([(a, 0.0)]);
+        let mut prev: HashMap<u32, u32> = HashMap::new();
+        let mut heap = BinaryHeap::from([Reverse(Entry(0.0, a))]);
+        while let Some(Reverse(Entry(cost, node))) = heap.pop() {
+            if node == b {
+                let mut path = vec![b];
+                while let Some(p) = prev.get(path.last().expect("path is non-empty")) {
+                    path.push(*p);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if cost > dist[&node] {
+                continue;
+            }
+            for (next, edge) in adjacency.get(&node).into_iter().flatten() {
+                let candidate = cost + edge;
+                if !matches!(dist.get(next), Some(d) if *d <= candidate) {
+                    dist.insert(*next, candidate);
+                    prev.insert(*next, node);
+                    heap.push(Reverse(Entry(candidate, *next)));
+                }
+            }
+        }
+        None
+    }
+
     /// Get related notes for a specific note using long-range link scores.
     ///
     /// If `long_range_links` are available, this returns neighbors with their spectral
     /// similarity scores ranked by descending similarity and limited by `top_k` when provided.
     /// If they are not available, this falls back to the stored `related_note_links` list
-    /// on the note.
+    /// on the note, scored by embedding cosine similarity (see
+    /// `get_related_note_links_with_metric`).
     pub fn get_related_note_links(&self, note_id: u32, top_k: Option<usize>) -> Vec<(u32, f32)> {
+        self.get_related_note_links_with_metric(note_id, top_k, RelatednessMetric::default())
+    }
+
+    /// Get related notes, choosing how the `related_note_links` fallback is scored.
+    ///
+    /// `metric` only applies when `long_range_links` are absent. With
+    /// `RelatednessMetric::EmbeddingCosine`, each linked note is scored by cosine similarity
+    /// of the stored embeddings and links to notes missing from the graph are dropped.
+    pub fn get_related_note_links_with_metric(
+        &self,
+        note_id: u32,
+        top_k: Option<usize>,
+        metric: RelatednessMetric,
+    ) -> Vec<(u32, f32)> {
         if let Some(links) = &self.long_range_links {
             let mut neighbors: HashMap<u32, f32> = HashMap::new();
             for (a, b, score) in links.iter() {
@@ -190,7 +490,18 @@ impl SpectralMemoryGraph {
         }
 
         if let Some(note) = self.notes.get(&note_id) {
-            let mut ids: Vec<(u32, f32)> = note.related_note_links.clone();
+            let mut ids: Vec<(u32, f32)> = match metric {
+                RelatednessMetric::StoredScore => note.related_note_links.clone(),
+                RelatednessMetric::EmbeddingCosine => note
+                    .related_note_links
+                    .iter()
+                    .filter_map(|(rid, _)| {
+                        self.notes
+                            .get(rid)
+                            .map(|related| (*rid, cosine_similarity(note, related)))
+                    })
+                    .collect(),
+            };
             ids.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
             if let Some(k) = top_k {
                 ids.truncate(k);
@@ -201,9 +512,139 @@ impl SpectralMemoryGraph {
         Vec::new()
     }
 
+    /// Compute pairwise cosine similarity among a small set of notes.
+    ///
+    /// Uses the stored embeddings and precomputed norms, so the cost is O(k²·d) for `k`
+    /// requested notes regardless of graph size and no full similarity matrix is built.
+    ///
+    /// # Arguments
+    ///
+    /// * `note_ids` - notes to compare; row/column `i` of the result corresponds to `note_ids[i]`
+    ///
+    /// # Returns
+    ///
+    /// A symmetric `k × k` matrix of cosine similarities. Rows and columns for unknown note ids
+    /// or zero-norm embeddings are left at `0.0`.
+    pub fn pairwise_similarity(&self, note_ids: &[u32]) -> Array2<f32> {
+        let k = note_ids.len();
+        let notes: Vec<Option<&SMGNote>> = note_ids.iter().map(|nid| self.notes.get(nid)).collect();
+        let mut sim = Array2::<f32>::zeros((k, k));
+        for i in 0..k {
+            let Some(a) = notes[i] else { continue };
+            for j in i..k {
+                let Some(b) = notes[j] else { continue };
+                let cosine = cosine_similarity(a, b);
+                sim[(i, j)] = cosine;
+                sim[(j, i)] = cosine;
+            }
+        }
+        sim
+    }
+
+    /// Compute pairwise cosine similarity between cluster centroids.
+    ///
+    /// Useful for mapping how topics relate to each other. Uses the stored centroid norms
+    /// when available and falls back to computing them from the centroid vectors.
+    ///
+    /// # Returns
+    ///
+    /// `None` if no centroids are present; otherwise the ascending cluster labels and a
+    /// symmetric matrix where row/column `i` corresponds to `labels[i]`.
+    pub fn cluster_centroid_similarity(&self) -> Option<(Vec<usize>, Array2<f32>)> {
+        let centroids = self.cluster_centroids.as_ref().filter(|c| !c.is_empty())?;
+        let mut labels: Vec<usize> = centroids.keys().cloned().collect();
+        labels.sort_unstable();
+
+        let norms: Vec<f32> = labels
+            .iter()
+            .map(|c| {
+                self.cluster_centroid_norms
+                    .as_ref()
+                    .and_then(|n| n.get(c).copied())
+                    .unwrap_or_else(|| centroids[c].iter().map(|x| x * x).sum::<f32>().sqrt())
+            })
+            .collect();
+
+        let k = labels.len();
+        let mut sim = Array2::<f32>::zeros((k, k));
+        for i in 0..k {
+            for j in i..k {
+                let cosine = vector_cosine(
+                    &centroids[&labels[i]],
+                    norms[i],
+                    &centroids[&labels[j]],
+                    norms[j],
+                );
+                sim[(i, j)] = cosine;
+                sim[(j, i)] = cosine;
+            }
+        }
+        Some((labels, sim))
+    }
+
+    /// Return the `top_n` most salient terms of cluster `label`, with their TF-IDF weight.
+    ///
+    /// Each cluster is treated as one document made of its members' `context()`. Term
+    /// frequency is normalised by the cluster's token count and IDF is smoothed
+    /// (`ln((1 + K) / (1 + df)) + 1` over `K` clusters). Tokens are lowercased
+    /// alphanumeric runs (`_` included) of at least three characters; pure numbers are
+    /// skipped. Ties are broken alphabetically.
+    ///
+    /// Returns an empty vector if labels are missing, stale, or `label` has no members.
+    pub fn cluster_top_terms(&self, label: usize, top_n: usize) -> Vec<(String, f32)> {
+        let Some(labels) = self.cluster_labels.as_ref() else {
+            return Vec::new();
+        };
+        if labels.len() != self.notes.len() || top_n == 0 {
+            return Vec::new();
+        }
+        let mut note_ids: Vec<u32> = self.notes.keys().cloned().collect();
+        note_ids.sort_unstable();
+
+        // Term counts per cluster.
+        let mut counts: HashMap<usize, HashMap<String, usize>> = HashMap::new();
+        for (nid, lbl) in note_ids.iter().zip(labels.iter()) {
+            let terms = counts.entry(*lbl).or_default();
+            for token in self.notes[nid]
+                .context()
+                .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .filter(|t| t.chars().count() >= 3 && !t.chars().all(|c| c.is_ascii_digit()))
+            {
+                *terms.entry(token.to_lowercase()).or_insert(0) += 1;
+            }
+        }
+        let Some(own) = counts.get(&label) else {
+            return Vec::new();
+        };
+        let total: usize = own.values().sum();
+        if total == 0 {
+            return Vec::new();
+        }
+
+        let num_clusters = counts.len() as f32;
+        let mut scored: Vec<(String, f32)> = own
+            .iter()
+            .map(|(term, count)| {
+                let df = counts.values().filter(|c| c.contains_key(term)).count() as f32;
+                let idf = ((1.0 + num_clusters) / (1.0 + df)).ln() + 1.0;
+                (term.clone(), (*count as f32 / total as f32) * idf)
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(top_n);
+        scored
+    }
+
     /// Ingest a conversation turn into the SMG as a single `SMGNote`.
     pub fn ingest_turn(&mut self, turn: &ConversationTurn) -> Result<()> {
         let emb = embed::get_embedding(&turn.content)?;
+        self.insert_turn_note(turn, emb);
+        Ok(())
+    }
+
+    /// Insert a new note for `turn` with a precomputed embedding.
+    fn insert_turn_note(&mut self, turn: &ConversationTurn, emb: Vec<f32>) {
+        self.mark_modified();
         let norm = emb.iter().map(|x| x * x).sum::<f32>().sqrt();
         let note = SMGNote {
             note_id: self.next_id,
@@ -219,10 +660,246 @@ impl SpectralMemoryGraph {
             ast_node_type: turn.ast_node_type.clone(),
             file_path: turn.file_path.clone(),
             structural_links: Vec::new(),
+            segment_index: turn.segment_index,
+            segment_count: turn.segment_count,
+            source_ingested_at: vec![turn.ingested_at_or_now()],
         };
         self.notes.insert(self.next_id, note);
         self.next_id += 1;
-        Ok(())
+    }
+
+    /// Ids of notes with `commit_id` among their source commits, ascending.
+    pub fn notes_for_commit(&self, commit_id: &str) -> Vec<u32> {
+        let mut ids: Vec<u32> = self
+            .notes
+            .values()
+            .filter(|note| {
+                note.source_commit_ids
+                    .iter()
+                    .any(|cid| cid.as_deref() == Some(commit_id))
+            })
+            .map(|note| note.note_id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Replace everything ingested from `commit_id` with freshly collected `turns`.
+    ///
+    /// Notes sourced only from the commit are removed; notes that also aggregate other
+    /// commits (e.g. via exact dedup) keep their content and just drop the commit's
+    /// provenance entries. The new turns are embedded up front, so an embedding failure
+    /// leaves the graph untouched, and receive turn ids above the current maximum.
+    ///
+    /// Spectral structures (similarity matrix, embeddings, clusters, centroids and
+    /// long-range links) are invalidated; rebuild them afterwards.
+    ///
+    /// # Returns
+    ///
+    /// The number of notes previously sourced from the commit and the ids of the newly
+    /// created notes.
+    pub fn reingest_commit(
+        &mut self,
+        commit_id: &str,
+        turns: &[ConversationTurn],
+    ) -> Result<(usize, Vec<u32>)> {
+        let contents: Vec<String> = turns.iter().map(|t| t.content.clone()).collect();
+        let embeddings = embed::get_embeddings(&contents, None)
+            .with_context(|| format!("embedding re-split turns of commit {}", commit_id))?;
+
+        let previous = self.notes_for_commit(commit_id);
+        let mut removed: std::collections::HashSet<u32> = std::collections::HashSet::new();
+        for &nid in &previous {
+            let note = self
+                .notes
+                .get_mut(&nid)
+                .with_context(|| format!("note {} of commit {} is missing", nid, commit_id))?;
+            let keep: Vec<bool> = note
+                .source_commit_ids
+                .iter()
+                .map(|cid| cid.as_deref() != Some(commit_id))
+                .collect();
+            if !keep.contains(&true) {
+                self.notes.remove(&nid);
+                removed.insert(nid);
+                continue;
+            }
+            let mut flags = keep.iter();
+            note.source_turn_ids.retain(|_| *flags.next().unwrap_or(&true));
+            let mut flags = keep.iter();
+            note.source_commit_ids.retain(|_| *flags.next().unwrap_or(&true));
+            let mut flags = keep.iter();
+            note.source_timestamps.retain(|_| *flags.next().unwrap_or(&true));
+            let mut flags = keep.iter();
+            note.source_ingested_at.retain(|_| *flags.next().unwrap_or(&true));
+        }
+        for note in self.notes.values_mut() {
+            note.related_note_links.retain(|(id, _)| !removed.contains(id));
+            note.structural_links.retain(|id| !removed.contains(id));
+        }
+        self.invalidate_spectral_structures();
+
+        let mut turn_id = self
+            .notes
+            .values()
+            .flat_map(|note| note.source_turn_ids.iter().copied())
+            .max()
+            .unwrap_or(0);
+        let mut new_ids = Vec::with_capacity(turns.len());
+        for (turn, emb) in turns.iter().zip(embeddings) {
+            turn_id += 1;
+            let mut turn = turn.clone();
+            turn.turn_id = turn_id;
+            new_ids.push(self.next_id);
+            self.insert_turn_note(&turn, emb);
+        }
+        Ok((previous.len(), new_ids))
+    }
+
+    /// Drop all cached spectral structures after the note set changed.
+    fn invalidate_spectral_structures(&mut self) {
+        self.mark_modified();
+        self.similarity_matrix = None;
+        self.similarity_note_ids = None;
+        self.spectral_embeddings = None;
+        self.cluster_labels = None;
+        self.cluster_centroids = None;
+        self.cluster_centroid_norms = None;
+        self.long_range_links = None;
+        if let Some(mut cache) = self.lock_query_cache() {
+            cache.clear();
+        }
+    }
+
+    /// Copy of the graph restricted to notes whose cluster label is in `labels`.
+    ///
+    /// Note ids and labels are preserved. Related, structural and long-range links are kept
+    /// only between retained notes, and centroids only for the allowed clusters. The
+    /// similarity matrix and spectral embeddings are dropped (their rows no longer line up).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the graph has no cluster labels covering every note.
+    pub fn subgraph_for_clusters(&self, labels: &[usize]) -> Result<SpectralMemoryGraph> {
+        let all_labels = match &self.cluster_labels {
+            Some(l) if l.len() == self.notes.len() => l,
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "graph has no cluster labels for its {} notes; build spectral structures first",
+                    self.notes.len()
+                ))
+            }
+        };
+        let mut note_ids: Vec<u32> = self.notes.keys().cloned().collect();
+        note_ids.sort_unstable();
+
+        let mut sub = SpectralMemoryGraph::new()?;
+        let mut kept_labels = Vec::new();
+        for (nid, lbl) in note_ids.iter().zip(all_labels.iter()) {
+            if labels.contains(lbl) {
+                sub.notes.insert(*nid, self.notes[nid].clone());
+                kept_labels.push(*lbl);
+            }
+        }
+        for note in sub.notes.values_mut() {
+            note.spectral_coords = None;
+            note.related_note_links.retain(|(id, _)| labels_contain(all_labels, &note_ids, *id, labels));
+            note.structural_links.retain(|id| labels_contain(all_labels, &note_ids, *id, labels));
+        }
+
+        sub.next_id = self.next_id;
+        sub.cluster_labels = Some(Array1::from(kept_labels));
+        sub.cluster_centroids = self.cluster_centroids.as_ref().map(|centroids| {
+            centroids
+                .iter()
+                .filter(|(c, _)| labels.contains(c))
+                .map(|(c, v)| (*c, v.clone()))
+                .collect()
+        });
+        sub.cluster_centroid_norms = self.cluster_centroid_norms.as_ref().map(|norms| {
+            norms
+                .iter()
+                .filter(|(c, _)| labels.contains(c))
+                .map(|(c, n)| (*c, *n))
+                .collect()
+        });
+        sub.long_range_links = self.long_range_links.as_ref().map(|links| {
+            links
+                .iter()
+                .filter(|(a, b, _)| sub.notes.contains_key(a) && sub.notes.contains_key(b))
+                .cloned()
+                .collect()
+        });
+        sub.last_build_config = self.last_build_config.clone();
+        sub.max_candidates_per_note = self.max_candidates_per_note;
+        sub.missing_timestamp_policy = self.missing_timestamp_policy;
+        Ok(sub)
+    }
+
+    /// Copy of the graph restricted to the notes of one cluster; see `subgraph_for_clusters`.
+    pub fn subgraph_for_cluster(&self, label: usize) -> Result<SpectralMemoryGraph> {
+        self.subgraph_for_clusters(&[label])
+    }
+
+    /// Add a turn to an already-built graph and assign it to the nearest cluster.
+    ///
+    /// The turn is embedded and inserted like `ingest_turn`, then labeled with the cluster
+    /// whose centroid is most cosine-similar to it. That centroid (and its norm) is updated
+    /// as a running mean, so the graph stays queryable without a full rebuild. The spectral
+    /// embeddings, similarity matrix and long-range links are not extended; call
+    /// `build_spectral_structure` to refresh them.
+    ///
+    /// # Returns
+    ///
+    /// The assigned cluster label, or `None` when the graph has no clusters covering every
+    /// existing note (the turn is still inserted).
+    pub fn add_turn_assign_cluster(&mut self, turn: &ConversationTurn) -> Result<Option<usize>> {
+        let clustered = matches!(
+            (&self.cluster_labels, &self.cluster_centroids),
+            (Some(l), Some(c)) if l.len() == self.notes.len() && !c.is_empty()
+        );
+        // Labels are aligned with sorted note ids, so the new id must sort last.
+        let appends_in_order = self.notes.keys().all(|nid| *nid < self.next_id);
+
+        let note_id = self.next_id;
+        self.ingest_turn(turn)?;
+        if !clustered || !appends_in_order {
+            return Ok(None);
+        }
+
+        let note = &self.notes[&note_id];
+        let centroids = self.cluster_centroids.as_mut().expect("checked above");
+        let norms = self.cluster_centroid_norms.get_or_insert_with(HashMap::new);
+        let label = centroids
+            .iter()
+            .map(|(c, vec)| {
+                let norm = *norms
+                    .entry(*c)
+                    .or_insert_with(|| vec.iter().map(|x| x * x).sum::<f32>().sqrt());
+                let sim = if norm == 0.0 || note.norm == 0.0 {
+                    0.0
+                } else {
+                    let dot: f32 = vec.iter().zip(note.embedding.iter()).map(|(a, b)| a * b).sum();
+                    dot / (norm * note.norm)
+                };
+                (*c, sim)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+            .map(|(c, _)| c)
+            .expect("at least one centroid");
+
+        let labels = self.cluster_labels.as_mut().expect("checked above");
+        let members = labels.iter().filter(|l| **l == label).count() as f32;
+        let centroid = centroids.get_mut(&label).expect("label from centroids");
+        for (c, x) in centroid.iter_mut().zip(note.embedding.iter()) {
+            *c += (x - *c) / (members + 1.0);
+        }
+        norms.insert(label, centroid.iter().map(|x| x * x).sum::<f32>().sqrt());
+
+        let mut extended = labels.to_vec();
+        extended.push(label);
+        *labels = Array1::from(extended);
+        Ok(Some(label))
     }
 
     /// Ingest multiple conversation turns using linear embedding.
@@ -242,10 +919,47 @@ impl SpectralMemoryGraph {
         &mut self,
         turns: &[ConversationTurn],
         progress: Option<ProgressCallback>,
+    ) -> Result<()> {
+        self.ingest_turns_batch_with_dedup(turns, progress, false)
+    }
+
+    /// Ingest multiple conversation turns, optionally merging exact-duplicate content.
+    ///
+    /// When `dedup_exact` is set, a turn whose content is byte-identical to the `raw_content`
+    /// of an existing note (or of a note created earlier in the same batch) is not inserted as
+    /// a new note. Instead its turn id, commit id and timestamp are appended to the existing
+    /// note via `SMGNote::record_source_turn`. Duplicates of existing notes are not re-embedded.
+    ///
+    /// # Arguments
+    ///
+    /// * `turns` - slice of `ConversationTurn` to ingest
+    /// * `progress` - optional progress callback that receives a message and progress fraction (0.0..1.0)
+    /// * `dedup_exact` - merge turns whose content exactly matches an existing note
+    ///
+    /// # Returns
+    ///
+    /// `Ok(())` on success, or an error if embedding fails.
+    pub fn ingest_turns_batch_with_dedup(
+        &mut self,
+        turns: &[ConversationTurn],
+        progress: Option<ProgressCallback>,
+        dedup_exact: bool,
     ) -> Result<()> {
         if turns.is_empty() {
             return Ok(());
         }
+        self.mark_modified();
+
+        // Content hash -> note ids carrying that exact content (only populated when deduplicating).
+        let mut content_index: HashMap<u64, Vec<u32>> = HashMap::new();
+        if dedup_exact {
+            for note in self.notes.values() {
+                content_index
+                    .entry(content_hash(&note.raw_content))
+                    .or_default()
+                    .push(note.note_id);
+            }
+        }
 
         // Extract unique texts for batch embedding (avoid redundant calls for AST symbols in same commit)
         let mut unique_texts = Vec::new();
@@ -253,6 +967,11 @@ impl SpectralMemoryGraph {
         let mut turn_to_unique_idx = Vec::with_capacity(turns.len());
 
         for turn in turns {
+            if dedup_exact && self.find_note_with_content(&content_index, &turn.content).is_some() {
+                // Merged into an existing note below; no embedding needed.
+                turn_to_unique_idx.push(usize::MAX);
+                continue;
+            }
             let idx = *text_to_idx.entry(turn.content.clone()).or_insert_with(|| {
                 let i = unique_texts.len();
                 unique_texts.push(turn.content.clone());
@@ -271,41 +990,82 @@ impl SpectralMemoryGraph {
             .with_context(|| "batch embedding unique turns")?;
 
         // Reconstruct notes with shared embeddings where possible
-        for (turn, &u_idx) in turns.iter().zip(turn_to_unique_idx.iter()) {
-            let emb = &unique_embeddings[u_idx];
-            let norm: f32 = emb.iter().map(|x: &f32| x * x).sum::<f32>().sqrt();
-            let note = SMGNote {
-                note_id: self.next_id,
-                raw_content: turn.content.clone(),
-                embedding: emb.clone(),
-                norm,
-                source_turn_ids: vec![turn.turn_id],
-                source_commit_ids: vec![turn.commit_id.clone()],
-                source_timestamps: vec![turn.timestamp],
-                spectral_coords: None,
-                related_note_links: Vec::new(),
-                symbol_id: turn.symbol_id.clone(),
-                ast_node_type: turn.ast_node_type.clone(),
-                file_path: turn.file_path.clone(),
-                structural_links: Vec::new(),
+        for (idx, (turn, &u_idx)) in turns.iter().zip(turn_to_unique_idx.iter()).enumerate() {
+            let existing = if dedup_exact {
+                self.find_note_with_content(&content_index, &turn.content)
+            } else {
+                None
             };
-            self.notes.insert(self.next_id, note);
-            self.next_id += 1;
+
+            if let Some(nid) = existing {
+                if let Some(note) = self.notes.get_mut(&nid) {
+                    note.record_source_turn(turn);
+                }
+            } else {
+                let emb = &unique_embeddings[u_idx];
+                let norm: f32 = emb.iter().map(|x: &f32| x * x).sum::<f32>().sqrt();
+                let note = SMGNote {
+                    note_id: self.next_id,
+                    raw_content: turn.content.clone(),
+                    embedding: emb.clone(),
+                    norm,
+                    source_turn_ids: vec![turn.turn_id],
+                    source_commit_ids: vec![turn.commit_id.clone()],
+                    source_timestamps: vec![turn.timestamp],
+                    spectral_coords: None,
+                    related_note_links: Vec::new(),
+                    symbol_id: turn.symbol_id.clone(),
+                    ast_node_type: turn.ast_node_type.clone(),
+                    file_path: turn.file_path.clone(),
+                    structural_links: Vec::new(),
+                    segment_index: turn.segment_index,
+                    segment_count: turn.segment_count,
+                    source_ingested_at: vec![turn.ingested_at_or_now()],
+                };
+                if dedup_exact {
+                    content_index
+                        .entry(content_hash(&turn.content))
+                        .or_default()
+                        .push(self.next_id);
+                }
+                self.notes.insert(self.next_id, note);
+                self.next_id += 1;
+            }
 
             // Update progress callback if provided
             // Reconstruction takes 50% of total progress (0.5 to 1.0)
             if let Some(ref cb) = progress {
-                let fraction = 0.5 + ((self.next_id - 1) as f32 / turns.len() as f32) * 0.5;
-                cb(format!("Ingested turn {}", self.next_id - 1), fraction);
+                let fraction = 0.5 + ((idx + 1) as f32 / turns.len() as f32) * 0.5;
+                cb(format!("Ingested turn {}", idx + 1), fraction);
             }
         }
 
         Ok(())
     }
 
+    /// Look up a note whose `raw_content` is byte-identical to `content` using a hash index.
+    fn find_note_with_content(
+        &self,
+        content_index: &HashMap<u64, Vec<u32>>,
+        content: &str,
+    ) -> Option<u32> {
+        content_index
+            .get(&content_hash(content))?
+            .iter()
+            .copied()
+            .filter(|nid| {
+                self.notes
+                    .get(nid)
+                    .map(|n| n.raw_content == content)
+                    .unwrap_or(false)
+            })
+            .min()
+    }
+
     /// Resolve structural links between notes sharing the same `symbol_id`.
     /// This should be called after ingestion to populate `structural_links`.
     pub fn resolve_structural_links(&mut self) {
+        self.mark_modified();
         let mut symbol_to_notes: HashMap<String, Vec<u32>> = HashMap::new();
         for note in self.notes.values() {
             if let Some(sid) = &note.symbol_id {
@@ -352,10 +1112,7 @@ impl SpectralMemoryGraph {
         config: &SpectralBuildConfig,
     ) -> Result<()> {
         use crate::graph::spectral::{
-            assemble_embedding_matrix, compute_centroids_in_embedding_space,
-            compute_spectral_embeddings, compute_fused_similarity_matrix, detect_long_range_links,
-            eigengap_heuristic, normalized_laplacian_sparse, run_kmeans_on_spectral, sparsify_adj,
-            spectral_decomposition_sparse, to_sparse,
+            assemble_embedding_matrix_with_budget, compute_fused_similarity_matrix,
         };
         
         self.last_build_config = Some(config.clone());
@@ -387,7 +1144,11 @@ impl SpectralMemoryGraph {
 
         // 1) Assemble embedding matrix (n x d).
         report_progress(1, TOTAL_STEPS, "Assembling embedding matrix".to_string());
-        let embed_mat = assemble_embedding_matrix(&self.notes, &note_ids);
+        let embed_mat = assemble_embedding_matrix_with_budget(
+            &self.notes,
+            &note_ids,
+            config.max_embedding_matrix_bytes,
+        )?;
 
         // 2) Fused similarity matrix (dense).
         report_progress(
@@ -395,7 +1156,7 @@ impl SpectralMemoryGraph {
             TOTAL_STEPS,
             "Computing fused similarity matrix (structural fusion)".to_string(),
         );
-        let mut sim = compute_fused_similarity_matrix(
+        let sim = compute_fused_similarity_matrix(
             &embed_mat,
             &note_ids,
             &self.notes,
@@ -404,28 +1165,143 @@ impl SpectralMemoryGraph {
             progress.as_deref(),
         );
 
-        // 3) Sparsify adjacency in-place (zero diagonal + threshold).
-        report_progress(3, TOTAL_STEPS, "Sparsifying adjacency matrix".to_string());
-        sparsify_adj(&mut sim, config.adj_sparse_threshold);
-        
-        // 3b) Convert to sparse matrix and drop dense background to save memory
-        let sim_sparse = to_sparse(&sim);
-        drop(sim); // Free up the large dense matrix (e.g. 25GB for 80k notes)
-        self.similarity_matrix = Some(sim_sparse.clone());
-
-        // 4) Normalized Laplacian (L_sym wrapper).
-        report_progress(4, TOTAL_STEPS, "Computing normalized Laplacian".to_string());
-        let lap = normalized_laplacian_sparse(&sim_sparse);
+        self.build_from_similarity(sim, &note_ids, config, &progress)
+    }
 
-        // 5) Eigen-decomposition.
-        report_progress(5, TOTAL_STEPS, "Performing eigen-decomposition".to_string());
-        let (eigenvalues, eigenvectors) = spectral_decomposition_sparse(&lap, config.num_spectral_dims)?;
+    /// Build spectral structures from a caller-supplied similarity matrix.
+    ///
+    /// Skips the embedding-similarity steps and runs sparsification, decomposition,
+    /// clustering, centroids and long-range link detection on `sim`, using the default
+    /// `SpectralBuildConfig`. Useful for domain-specific similarities (e.g. from a clone
+    /// detector).
+    ///
+    /// # Arguments
+    ///
+    /// * `sim` - symmetric `n × n` similarity matrix over all `n` notes
+    /// * `order` - note id of each row/column of `sim`; must contain every note exactly once
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `sim` is not square or `order` does not match the note set.
+    pub fn build_spectral_structure_from_matrix(
+        &mut self,
+        sim: Array2<f32>,
+        order: &[u32],
+    ) -> Result<()> {
+        self.build_spectral_structure_from_matrix_with_config(
+            sim,
+            order,
+            None,
+            &SpectralBuildConfig::default(),
+        )
+    }
 
-        // 6) Spectral embeddings: take leading `k` eigenvectors and row-normalize.
-        report_progress(6, TOTAL_STEPS, "Extracting spectral embeddings".to_string());
-        let n_components = std::cmp::min(config.num_spectral_dims, n.saturating_sub(1));
-        let spectral_emb = compute_spectral_embeddings(&eigenvectors, n_components, true);
-        self.spectral_embeddings = Some(spectral_emb.clone());
+    /// Like `build_spectral_structure_from_matrix`, with progress reporting and a custom config.
+    pub fn build_spectral_structure_from_matrix_with_config(
+        &mut self,
+        sim: Array2<f32>,
+        order: &[u32],
+        progress: Option<ProgressCallback>,
+        config: &SpectralBuildConfig,
+    ) -> Result<()> {
+        // Reject the config before recording it, so a failed build is never persisted as
+        // the last build configuration.
+        config.validate()?;
+        let n = self.notes.len();
+        if sim.nrows() != sim.ncols() {
+            return Err(anyhow::anyhow!(
+                "similarity matrix must be square, got {}x{}",
+                sim.nrows(),
+                sim.ncols()
+            ));
+        }
+        if sim.nrows() != n || order.len() != n {
+            return Err(anyhow::anyhow!(
+                "similarity matrix is {}x{} with {} ids in order, but the graph has {} notes",
+                sim.nrows(),
+                sim.ncols(),
+                order.len(),
+                n
+            ));
+        }
+        let mut position: HashMap<u32, usize> = HashMap::with_capacity(n);
+        for (i, nid) in order.iter().enumerate() {
+            if !self.notes.contains_key(nid) {
+                return Err(anyhow::anyhow!("order contains unknown note_id {}", nid));
+            }
+            if position.insert(*nid, i).is_some() {
+                return Err(anyhow::anyhow!("order contains note_id {} more than once", nid));
+            }
+        }
+
+        self.last_build_config = Some(config.clone());
+        if n < 3 {
+            // Drop structures from an earlier, larger build; they no longer match `sim`.
+            self.invalidate_spectral_structures();
+            if let Some(ref cb) = progress {
+                cb("Graph too small for spectral analysis".to_string(), 1.0);
+            }
+            return Ok(());
+        }
+
+        // Permute into sorted note-id order, which the cluster labels are indexed by.
+        let mut note_ids: Vec<u32> = order.to_vec();
+        note_ids.sort_unstable();
+        let sorted_sim = Array2::from_shape_fn((n, n), |(i, j)| {
+            sim[(position[&note_ids[i]], position[&note_ids[j]])]
+        });
+        self.build_from_similarity(sorted_sim, &note_ids, config, &progress)
+    }
+
+    /// Run the spectral pipeline (steps 3-10) on a dense similarity matrix whose rows
+    /// follow `note_ids` (sorted ascending).
+    fn build_from_similarity(
+        &mut self,
+        mut sim: Array2<f32>,
+        note_ids: &[u32],
+        config: &SpectralBuildConfig,
+        progress: &Option<ProgressCallback>,
+    ) -> Result<()> {
+        use crate::graph::spectral::{
+            compute_centroids_in_embedding_space, compute_spectral_embeddings,
+            detect_long_range_links, eigengap_heuristic, normalized_laplacian_sparse,
+            run_kmeans_on_spectral_with_params, sparsify_adj, spectral_decomposition_sparse,
+            to_sparse,
+        };
+
+        self.mark_modified();
+        let n = note_ids.len();
+        let report_progress = |step: usize, total_steps: usize, msg: String| {
+            if let Some(ref cb) = progress {
+                let fraction = (step as f32) / (total_steps as f32);
+                cb(msg, fraction);
+            }
+        };
+        const TOTAL_STEPS: usize = 10;
+
+        // 3) Sparsify adjacency in-place (zero diagonal + threshold).
+        report_progress(3, TOTAL_STEPS, "Sparsifying adjacency matrix".to_string());
+        sparsify_adj(&mut sim, config.adj_sparse_threshold);
+        
+        // 3b) Convert to sparse matrix and drop dense background to save memory
+        let sim_sparse = to_sparse(&sim);
+        drop(sim); // Free up the large dense matrix (e.g. 25GB for 80k notes)
+        self.similarity_matrix = Some(sim_sparse.clone());
+        self.similarity_note_ids = Some(note_ids.to_vec());
+
+        // 4) Normalized Laplacian (L_sym wrapper).
+        report_progress(4, TOTAL_STEPS, "Computing normalized Laplacian".to_string());
+        let lap = normalized_laplacian_sparse(&sim_sparse);
+
+        // 5) Eigen-decomposition.
+        report_progress(5, TOTAL_STEPS, "Performing eigen-decomposition".to_string());
+        let (eigenvalues, eigenvectors) = spectral_decomposition_sparse(&lap, config.num_spectral_dims)?;
+
+        // 6) Spectral embeddings: take leading `k` eigenvectors and row-normalize.
+        report_progress(6, TOTAL_STEPS, "Extracting spectral embeddings".to_string());
+        let n_components = std::cmp::min(config.num_spectral_dims, n.saturating_sub(1));
+        let spectral_emb = compute_spectral_embeddings(&eigenvectors, n_components, true);
+        self.spectral_embeddings = Some(spectral_emb.clone());
 
         // 7) Decide number of clusters.
         report_progress(
@@ -442,13 +1318,18 @@ impl SpectralMemoryGraph {
 
         // 8) K-Means on spectral embeddings.
         report_progress(8, TOTAL_STEPS, "Running K-Means clustering".to_string());
-        let labels = run_kmeans_on_spectral(&spectral_emb, n_clusters)?;
+        let labels = run_kmeans_on_spectral_with_params(
+            &spectral_emb,
+            n_clusters,
+            config.kmeans_max_iters,
+            config.kmeans_tolerance,
+        )?;
         self.cluster_labels = Some(labels.clone());
 
         // 9) Compute centroids in original embedding space.
         report_progress(9, TOTAL_STEPS, "Computing cluster centroids".to_string());
         let centroids_map =
-            compute_centroids_in_embedding_space(&labels, note_ids.as_slice(), &self.notes);
+            compute_centroids_in_embedding_space(&labels, note_ids, &self.notes);
         self.cluster_centroids = Some(centroids_map.clone());
 
         // Precompute centroid norms for fast cosine similarity during queries
@@ -471,12 +1352,13 @@ impl SpectralMemoryGraph {
                 .expect("similarity matrix set"),
             config.spectral_link_similarity_threshold,
             config.embed_link_similarity_threshold,
-            note_ids.as_slice(),
+            note_ids,
             &self.notes,
-            None, // no top-k limit during build
+            config.max_links,
         );
-        // Store the links with scores for later retrieval
+        // Store the links with scores for later retrieval, in paging order.
         self.long_range_links = Some(pairs.clone());
+        self.sort_long_range_links();
 
         // Also populate per-note related links for persistence and fallback retrieval.
         // Reset first to prevent stale links from accumulating across repeated rebuilds.
@@ -512,14 +1394,40 @@ impl SpectralMemoryGraph {
         file_filter: Option<&str>,
         symbol_filter: Option<&str>,
         keyword_weight: f32,
+    ) -> Result<Vec<crate::temporal::Candidate>> {
+        self.retrieve_candidates_until(
+            query,
+            candidate_note_k,
+            file_filter,
+            symbol_filter,
+            keyword_weight,
+            None,
+        )
+    }
+
+    /// `retrieve_candidates` bounded by an optional deadline.
+    ///
+    /// With a deadline, notes are scored in parallel chunks of `DEADLINE_CHUNK_NOTES` and
+    /// the deadline is checked once per chunk (and again before candidate expansion); once
+    /// any chunk sees it pass, the remaining chunks are skipped and a `RetrievalTimeout`
+    /// error is returned. A chunk that has started always finishes, so the overrun is
+    /// bounded by the time to score one chunk. Without a deadline, scoring is a plain
+    /// parallel pass over the notes.
+    pub fn retrieve_candidates_until(
+        &self,
+        query: &str,
+        candidate_note_k: usize,
+        file_filter: Option<&str>,
+        symbol_filter: Option<&str>,
+        keyword_weight: f32,
+        deadline: Option<Instant>,
     ) -> Result<Vec<crate::temporal::Candidate>> {
         use rayon::prelude::*;
+        use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
         // Embed query.
-        let query_emb = embed::get_embedding(query)?;
-        // Use ndarray operations for efficient norm computation
-        let query_arr = Array1::from(query_emb);
-        let norm_q = query_arr.dot(&query_arr).sqrt();
+        let query_emb = embedding_cache::query_embedding(query)?;
+        let norm_q = query_emb.iter().map(|x| x * x).sum::<f32>().sqrt();
 
         // Stable ordering of notes (sort by note_id).
         let note_ids: Vec<u32> = {
@@ -529,54 +1437,78 @@ impl SpectralMemoryGraph {
         };
 
         // Compute raw cosine similarity per note (note-level score) using precomputed norms.
-        // Use parallel iteration for better performance on multi-core systems.
-        let mut scores: Vec<(usize, f32)> = note_ids
-            .par_iter()
-            .enumerate()
-            .map(|(i, nid)| {
-                let note = &self.notes[nid];
-                // Use ndarray operations for efficient dot product computation
-                let note_arr = Array1::from(note.embedding.clone());
-                let dot = note_arr.dot(&query_arr);
-                let raw_sim = if note.norm == 0.0 || norm_q == 0.0 {
-                    0.0
-                } else {
-                    dot / (note.norm * norm_q)
-                };
-
-                // Hybrid scoring: boost based on symbol/file metadata if query matches
-                let mut score = raw_sim;
-                if keyword_weight > 0.0 {
-                    let mut boost = 1.0;
-                    let q_lower = query.to_lowercase();
-                    if let Some(sid) = &note.symbol_id {
-                        if sid.to_lowercase().contains(&q_lower) {
-                            boost += keyword_weight;
-                        }
+        let score_note = |i: usize, nid: &u32| -> (usize, f32) {
+            let note = &self.notes[nid];
+            let raw_sim = vector_cosine(&note.embedding, note.norm, &query_emb, norm_q);
+
+            // Hybrid scoring: boost based on symbol/file metadata if query matches
+            let mut score = raw_sim;
+            if keyword_weight > 0.0 {
+                let mut boost = 1.0;
+                let q_lower = query.to_lowercase();
+                if let Some(sid) = &note.symbol_id {
+                    if sid.to_lowercase().contains(&q_lower) {
+                        boost += keyword_weight;
                     }
-                    if let Some(fp) = &note.file_path {
-                        if fp.to_lowercase().contains(&q_lower) {
-                            boost += keyword_weight;
-                        }
+                }
+                if let Some(fp) = &note.file_path {
+                    if fp.to_lowercase().contains(&q_lower) {
+                        boost += keyword_weight;
                     }
-                    score *= boost;
                 }
+                score *= boost;
+            }
 
-                // Hard filters for file/symbol
-                if let Some(ff) = file_filter {
-                    if !note.file_path.as_deref().unwrap_or("").contains(ff) {
-                        score = 0.0;
-                    }
+            // Hard filters for file/symbol
+            if let Some(ff) = file_filter {
+                if !note.file_path.as_deref().unwrap_or("").contains(ff) {
+                    score = 0.0;
                 }
-                if let Some(sf) = symbol_filter {
-                    if !note.symbol_id.as_deref().unwrap_or("").contains(sf) {
-                        score = 0.0;
-                    }
+            }
+            if let Some(sf) = symbol_filter {
+                if !note.symbol_id.as_deref().unwrap_or("").contains(sf) {
+                    score = 0.0;
                 }
+            }
 
-                (i, score)
-            })
-            .collect();
+            (i, score)
+        };
+        // Use parallel iteration for better performance on multi-core systems.
+        let mut scores: Vec<(usize, f32)> = if deadline.is_none() {
+            note_ids
+                .par_iter()
+                .enumerate()
+                .map(|(i, nid)| score_note(i, nid))
+                .collect()
+        } else {
+            // Check the deadline per chunk; the first chunk to see it pass stops the rest.
+            let timed_out = AtomicBool::new(false);
+            let scored = AtomicUsize::new(0);
+            note_ids
+                .par_chunks(DEADLINE_CHUNK_NOTES)
+                .enumerate()
+                .map(|(chunk, nids)| {
+                    if timed_out.load(Ordering::Relaxed) || deadline_passed(deadline) {
+                        timed_out.store(true, Ordering::Relaxed);
+                        return None;
+                    }
+                    let offset = chunk * DEADLINE_CHUNK_NOTES;
+                    let chunk_scores: Vec<(usize, f32)> = nids
+                        .iter()
+                        .enumerate()
+                        .map(|(j, nid)| score_note(offset + j, nid))
+                        .collect();
+                    scored.fetch_add(nids.len(), Ordering::Relaxed);
+                    Some(chunk_scores)
+                })
+                .collect::<Option<Vec<Vec<(usize, f32)>>>>()
+                .ok_or(RetrievalTimeout {
+                    notes_scored: scored.load(Ordering::Relaxed),
+                })?
+                .into_iter()
+                .flatten()
+                .collect()
+        };
 
         // Apply centroid-based boosting if clusters exist.
         // Use precomputed centroid norms for fast cosine similarity.
@@ -585,18 +1517,11 @@ impl SpectralMemoryGraph {
             &self.cluster_centroids,
             &self.cluster_centroid_norms,
         ) {
-            // Compute centroid scores using precomputed norms and ndarray operations
+            // Compute centroid scores using precomputed norms
             let mut centroid_scores: Vec<(usize, f32)> = Vec::new();
             for (c, centroid_vec) in centroids.iter() {
-                let centroid_arr = Array1::from(centroid_vec.clone());
-                let dot = centroid_arr.dot(&query_arr);
-                // Use precomputed centroid norm
                 let norm_c = centroid_norms.get(c).copied().unwrap_or(0.0);
-                let c_sim = if norm_c == 0.0 || norm_q == 0.0 {
-                    0.0
-                } else {
-                    dot / (norm_c * norm_q)
-                };
+                let c_sim = vector_cosine(centroid_vec, norm_c, &query_emb, norm_q);
                 centroid_scores.push((*c, c_sim));
             }
             centroid_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
@@ -615,34 +1540,280 @@ impl SpectralMemoryGraph {
 
         // Rank notes by score and take top candidate_note_k notes.
         scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        if deadline_passed(deadline) {
+            return Err(RetrievalTimeout { notes_scored: scores.len() }.into());
+        }
 
         // Expand top notes into candidates using parallel iteration for better performance
         let candidates: Vec<crate::temporal::Candidate> = scores
             .par_iter()
             .take(candidate_note_k)
-            .flat_map(|(idx, score)| {
-                let nid = note_ids[*idx];
-                if let Some(note) = self.notes.get(&nid) {
-                    note.source_turn_ids
-                        .iter()
-                        .enumerate()
-                        .map(move |(i, tid)| {
-                            let ts = note.source_timestamps.get(i).cloned();
-                            crate::temporal::Candidate {
-                                turn_id: *tid,
-                                note_id: nid,
-                                raw_score: *score,
-                                timestamp: ts,
-                            }
-                        })
-                        .collect::<Vec<_>>()
-                } else {
-                    Vec::new()
-                }
+            .flat_map(|(idx, score)| match self.notes.get(&note_ids[*idx]) {
+                Some(note) => self.note_candidates(note, *score),
+                None => Vec::new(),
+            })
+            .collect();
+        Ok(candidates)
+    }
+
+    /// Retrieve candidates by scoring only members of the clusters nearest to the query.
+    ///
+    /// Clusters are ranked by cosine similarity between the query embedding and each cluster
+    /// centroid, and only notes labeled with one of the top `probe_clusters` clusters are
+    /// scored. This is a cheap approximate alternative to the full scan of
+    /// `retrieve_candidates` for large graphs. Scores are pure semantic similarity (no
+    /// keyword or cluster boost). Falls back to a full scan when cluster labels or centroids
+    /// are absent or no longer cover every note.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - query string to search for
+    /// * `candidate_note_k` - number of top-scoring notes to expand into per-turn candidates
+    /// * `probe_clusters` - number of nearest clusters whose members are scored (at least 1)
+    ///
+    /// # Returns
+    ///
+    /// A list of `temporal::Candidate` ordered by descending note score.
+    pub fn retrieve_candidates_routed(
+        &self,
+        query: &str,
+        candidate_note_k: usize,
+        probe_clusters: usize,
+    ) -> Result<Vec<crate::temporal::Candidate>> {
+        use rayon::prelude::*;
+
+        let (labels, centroids, centroid_norms) = match (
+            &self.cluster_labels,
+            &self.cluster_centroids,
+            &self.cluster_centroid_norms,
+        ) {
+            (Some(l), Some(c), Some(n)) if l.len() == self.notes.len() && !c.is_empty() => (l, c, n),
+            _ => return self.retrieve_candidates(query, candidate_note_k, None, None, 0.0),
+        };
+
+        let query_emb = embedding_cache::query_embedding(query)?;
+        let norm_q = query_emb.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+        // Rank clusters by centroid similarity (ties broken by label for determinism).
+        let mut cluster_scores: Vec<(usize, f32)> = centroids
+            .iter()
+            .map(|(c, vec)| {
+                let norm_c = centroid_norms.get(c).copied().unwrap_or(0.0);
+                (*c, vector_cosine(vec, norm_c, &query_emb, norm_q))
+            })
+            .collect();
+        cluster_scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        let probed: std::collections::HashSet<usize> = cluster_scores
+            .iter()
+            .take(probe_clusters.max(1))
+            .map(|(c, _)| *c)
+            .collect();
+
+        // Cluster labels are aligned with the sorted note-id order.
+        let mut note_ids: Vec<u32> = self.notes.keys().cloned().collect();
+        note_ids.sort_unstable();
+        let members: Vec<u32> = note_ids
+            .iter()
+            .zip(labels.iter())
+            .filter(|(_, lbl)| probed.contains(lbl))
+            .map(|(nid, _)| *nid)
+            .collect();
+        let mut scores: Vec<(u32, f32)> = members
+            .par_iter()
+            .map(|nid| {
+                let note = &self.notes[nid];
+                (*nid, vector_cosine(&note.embedding, note.norm, &query_emb, norm_q))
             })
             .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let candidates = scores
+            .iter()
+            .take(candidate_note_k)
+            .flat_map(|(nid, score)| self.note_candidates(&self.notes[nid], *score))
+            .collect();
         Ok(candidates)
     }
+
+    /// Stream candidates over `channel` in batches of descending score.
+    ///
+    /// Scores never increase from one batch to the next, so a caller can stop at a score
+    /// threshold (or once it has enough candidates) by dropping the receiver; scanning
+    /// stops at the next batch. Scores are pure semantic similarity. The stream ends when
+    /// this method returns and `channel` is dropped.
+    ///
+    /// When cluster labels and centroids cover every note, each cluster gets an upper
+    /// bound on its members' similarity to the query (from the centroid similarity and the
+    /// cluster's angular radius, see `cluster_radii`). Clusters are scored in order of
+    /// descending bound, in chunks of at most `STREAM_BATCH_NOTES` notes, and after each
+    /// chunk every scored note at or above the bound of the unscored clusters is sent.
+    /// Without clusters there is no such bound: every note is scored before the first
+    /// batch, and the global ranking is streamed in batches of `STREAM_BATCH_NOTES` notes.
+    pub fn retrieve_candidates_streaming(
+        &self,
+        query: &str,
+        channel: std::sync::mpsc::Sender<Vec<crate::temporal::Candidate>>,
+    ) -> Result<()> {
+        use rayon::prelude::*;
+
+        let query_emb = embedding_cache::query_embedding(query)?;
+        let norm_q = query_emb.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+        let mut note_ids: Vec<u32> = self.notes.keys().cloned().collect();
+        note_ids.sort_unstable();
+
+        let score_note = |nid: &u32| -> (u32, f32) {
+            let note = &self.notes[nid];
+            (*nid, vector_cosine(&note.embedding, note.norm, &query_emb, norm_q))
+        };
+        let by_score = |a: &(u32, f32), b: &(u32, f32)| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0));
+        // Send ranked notes in batches; false once the receiver has hung up.
+        let send = |ranked: &[(u32, f32)]| -> bool {
+            ranked.chunks(STREAM_BATCH_NOTES).all(|batch| {
+                let candidates: Vec<crate::temporal::Candidate> = batch
+                    .iter()
+                    .flat_map(|(nid, score)| self.note_candidates(&self.notes[nid], *score))
+                    .collect();
+                candidates.is_empty() || channel.send(candidates).is_ok()
+            })
+        };
+
+        let clusters = match (
+            &self.cluster_labels,
+            &self.cluster_centroids,
+            &self.cluster_centroid_norms,
+        ) {
+            (Some(labels), Some(centroids), Some(norms))
+                if labels.len() == note_ids.len() && !centroids.is_empty() =>
+            {
+                Some((labels, centroids, norms))
+            }
+            _ => None,
+        };
+        let Some((labels, centroids, norms)) = clusters else {
+            let mut scores: Vec<(u32, f32)> = note_ids.par_iter().map(score_note).collect();
+            scores.sort_by(by_score);
+            send(&scores);
+            return Ok(());
+        };
+
+        // Every member x of cluster c satisfies angle(q, x) >= angle(q, c) - radius(c), so
+        // cos(max(0, angle(q, c) - radius(c))) bounds its score. Labels without a centroid
+        // (or a zero vector) get the trivial bound 1.0.
+        let radii = self.cluster_radii();
+        let bound = |c: usize| -> f32 {
+            let (Some(vec), Some(radius)) = (centroids.get(&c), radii.get(&c)) else {
+                return 1.0;
+            };
+            let norm_c = norms.get(&c).copied().unwrap_or(0.0);
+            if norm_c == 0.0 || norm_q == 0.0 {
+                return 1.0;
+            }
+            let angle = vector_cosine(vec, norm_c, &query_emb, norm_q).clamp(-1.0, 1.0).acos();
+            // Zero-vector members score 0.0; the slack absorbs rounding in acos/cos.
+            ((angle - radius).max(0.0).cos() + STREAM_BOUND_SLACK).clamp(0.0, 1.0)
+        };
+
+        let mut members: HashMap<usize, Vec<u32>> = HashMap::new();
+        for (nid, label) in note_ids.iter().zip(labels.iter()) {
+            members.entry(*label).or_default().push(*nid);
+        }
+        let mut order: Vec<(f32, usize)> = members.keys().map(|c| (bound(*c), *c)).collect();
+        order.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        // (bound of the chunk's cluster, note ids), in visit order.
+        let chunks: Vec<(f32, &[u32])> = order
+            .iter()
+            .flat_map(|(b, c)| members[c].chunks(STREAM_BATCH_NOTES).map(move |chunk| (*b, chunk)))
+            .collect();
+
+        let mut pending: Vec<(u32, f32)> = Vec::new();
+        for (i, (_, chunk)) in chunks.iter().enumerate() {
+            pending.extend(chunk.par_iter().map(score_note).collect::<Vec<_>>());
+            pending.sort_by(by_score);
+            // Chunks are visited by descending bound, so the next one bounds all unscored notes.
+            let remaining = chunks.get(i + 1).map_or(f32::NEG_INFINITY, |(b, _)| *b);
+            let ready = pending.iter().take_while(|(_, score)| *score >= remaining).count();
+            if !send(&pending[..ready]) {
+                // Receiver hung up: the caller has enough candidates.
+                break;
+            }
+            pending.drain(..ready);
+        }
+        Ok(())
+    }
+
+    /// Largest angle (radians) between a member's embedding and its cluster centroid, per
+    /// cluster label. Members or centroids with a zero vector are skipped.
+    ///
+    /// Computed on first use and cached until `generation()` changes.
+    fn cluster_radii(&self) -> ClusterRadii {
+        let mut cached = self
+            .cluster_radii
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((generation, radii)) = cached.as_ref() {
+            if *generation == self.generation {
+                return radii.clone();
+            }
+        }
+
+        let mut radii: HashMap<usize, f32> = HashMap::new();
+        if let (Some(labels), Some(centroids), Some(norms)) = (
+            &self.cluster_labels,
+            &self.cluster_centroids,
+            &self.cluster_centroid_norms,
+        ) {
+            let mut note_ids: Vec<u32> = self.notes.keys().cloned().collect();
+            note_ids.sort_unstable();
+            for (nid, label) in note_ids.iter().zip(labels.iter()) {
+                let note = &self.notes[nid];
+                let (Some(centroid), Some(&norm_c)) = (centroids.get(label), norms.get(label)) else {
+                    continue;
+                };
+                if note.norm == 0.0 || norm_c == 0.0 {
+                    continue;
+                }
+                let angle = vector_cosine(&note.embedding, note.norm, centroid, norm_c)
+                    .clamp(-1.0, 1.0)
+                    .acos();
+                let radius = radii.entry(*label).or_insert(0.0);
+                *radius = radius.max(angle);
+            }
+        }
+        let radii = Arc::new(radii);
+        *cached = Some((self.generation, radii.clone()));
+        radii
+    }
+
+    /// Expand a scored note into one candidate per source turn.
+    ///
+    /// With `max_candidates_per_note` set, only the most recent turns (by timestamp, then
+    /// turn id) are kept, in their original order.
+    fn note_candidates(&self, note: &SMGNote, score: f32) -> Vec<crate::temporal::Candidate> {
+        let mut indices: Vec<usize> = (0..note.source_turn_ids.len()).collect();
+        if let Some(cap) = self.max_candidates_per_note {
+            if indices.len() > cap {
+                indices.sort_by(|&a, &b| {
+                    let ts = |i: usize| note.source_timestamps.get(i).copied().unwrap_or(0);
+                    ts(b).cmp(&ts(a))
+                        .then_with(|| note.source_turn_ids[b].cmp(&note.source_turn_ids[a]))
+                });
+                indices.truncate(cap);
+                indices.sort_unstable();
+            }
+        }
+        indices
+            .into_iter()
+            .map(|i| crate::temporal::Candidate {
+                turn_id: note.source_turn_ids[i],
+                note_id: note.note_id,
+                raw_score: score,
+                timestamp: note.source_timestamps.get(i).cloned(),
+                ingested_at: note.source_ingested_at.get(i).cloned(),
+            })
+            .collect()
+    }
+
     /// Search the graph using a text query, retrieving top results with scores.
     pub fn search(&self, query: &str, top_k: usize, min_score: Option<f32>) -> Result<Vec<(f32, u32)>> {
         let results = self.retrieve_with_scores_config(query, top_k, None, None, None, 0.3)?;
@@ -692,10 +1863,8 @@ impl SpectralMemoryGraph {
         use rayon::prelude::*;
 
         // Embed query.
-        let query_emb = embed::get_embedding(query)?;
-        // Use ndarray operations for efficient norm computation
-        let query_arr = Array1::from(query_emb);
-        let norm_q = query_arr.dot(&query_arr).sqrt();
+        let query_emb = embedding_cache::query_embedding(query)?;
+        let norm_q = query_emb.iter().map(|x| x * x).sum::<f32>().sqrt();
 
         // Use the provided filtered note IDs (assume they're already sorted)
         let note_ids: Vec<u32> = filtered_note_ids.to_vec();
@@ -707,14 +1876,7 @@ impl SpectralMemoryGraph {
             .enumerate()
             .map(|(i, nid)| {
                 let note = &self.notes[nid];
-                // Use ndarray operations for efficient dot product computation
-                let note_arr = Array1::from(note.embedding.clone());
-                let dot = note_arr.dot(&query_arr);
-                let raw_sim = if note.norm == 0.0 || norm_q == 0.0 {
-                    0.0
-                } else {
-                    dot / (note.norm * norm_q)
-                };
+                let raw_sim = vector_cosine(&note.embedding, note.norm, &query_emb, norm_q);
 
                 // Hybrid scoring: boost based on symbol/file metadata if query matches
                 let mut score = raw_sim;
@@ -757,18 +1919,11 @@ impl SpectralMemoryGraph {
             &self.cluster_centroids,
             &self.cluster_centroid_norms,
         ) {
-            // Compute centroid scores using precomputed norms and ndarray operations
+            // Compute centroid scores using precomputed norms
             let mut centroid_scores: Vec<(usize, f32)> = Vec::new();
             for (c, centroid_vec) in centroids.iter() {
-                let centroid_arr = Array1::from(centroid_vec.clone());
-                let dot = centroid_arr.dot(&query_arr);
-                // Use precomputed centroid norm
                 let norm_c = centroid_norms.get(c).copied().unwrap_or(0.0);
-                let c_sim = if norm_c == 0.0 || norm_q == 0.0 {
-                    0.0
-                } else {
-                    dot / (norm_c * norm_q)
-                };
+                let c_sim = vector_cosine(centroid_vec, norm_c, &query_emb, norm_q);
                 centroid_scores.push((*c, c_sim));
             }
             centroid_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
@@ -792,25 +1947,9 @@ impl SpectralMemoryGraph {
         let candidates: Vec<crate::temporal::Candidate> = scores
             .par_iter()
             .take(candidate_note_k)
-            .flat_map(|(idx, score)| {
-                let nid = note_ids[*idx];
-                if let Some(note) = self.notes.get(&nid) {
-                    note.source_turn_ids
-                        .iter()
-                        .enumerate()
-                        .map(move |(i, tid)| {
-                            let ts = note.source_timestamps.get(i).cloned();
-                            crate::temporal::Candidate {
-                                turn_id: *tid,
-                                note_id: nid,
-                                raw_score: *score,
-                                timestamp: ts,
-                            }
-                        })
-                        .collect::<Vec<_>>()
-                } else {
-                    Vec::new()
-                }
+            .flat_map(|(idx, score)| match self.notes.get(&note_ids[*idx]) {
+                Some(note) => self.note_candidates(note, *score),
+                None => Vec::new(),
             })
             .collect();
         Ok(candidates)
@@ -825,7 +1964,30 @@ impl SpectralMemoryGraph {
         self.retrieve_with_scores_config(query, top_k, None, None, None, 0.3)
     }
 
+    /// Retrieve with a temporal configuration given as JSON.
+    ///
+    /// Convenience for services that store temporal presets as JSON: `cfg_json` is
+    /// deserialized into a `TemporalConfig` and retrieval delegates to
+    /// `retrieve_with_scores_config` with the same defaults as `retrieve_with_scores`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `cfg_json` is not a valid `TemporalConfig`.
+    pub fn retrieve_with_scores_json_config(
+        &self,
+        query: &str,
+        top_k: usize,
+        cfg_json: &str,
+    ) -> Result<Vec<(u64, f32)>> {
+        let cfg: crate::temporal::TemporalConfig = serde_json::from_str(cfg_json)
+            .with_context(|| format!("invalid temporal config JSON: {}", cfg_json))?;
+        self.retrieve_with_scores_config(query, top_k, Some(cfg), None, None, 0.3)
+    }
+
     /// Retrieve with a specific temporal configuration.
+    ///
+    /// Served from the query cache when `enable_query_cache` was called and an entry for
+    /// the same query and configuration exists for the current `generation()`.
     pub fn retrieve_with_scores_config(
         &self,
         query: &str,
@@ -835,13 +1997,103 @@ impl SpectralMemoryGraph {
         symbol_filter: Option<&str>,
         keyword_weight: f32,
     ) -> Result<Vec<(u64, f32)>> {
-        let candidates = self.retrieve_candidates(query, top_k * 4, file_filter, symbol_filter, keyword_weight)?;
+        self.retrieve_with_scores_config_until(
+            query,
+            top_k,
+            temporal_cfg,
+            file_filter,
+            symbol_filter,
+            keyword_weight,
+            None,
+        )
+    }
+
+    /// `retrieve_with_scores_config` with a deadline, for serving under a latency budget.
+    ///
+    /// Pass `Some(Instant::now() + timeout)` to bound a query by a timeout. Cache hits are
+    /// returned without checking the deadline; results computed in time are cached as usual.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `RetrievalTimeout` error (see `retrieve_candidates_until`) if the deadline
+    /// passes during scoring.
+    #[allow(clippy::too_many_arguments)]
+    pub fn retrieve_with_scores_config_until(
+        &self,
+        query: &str,
+        top_k: usize,
+        temporal_cfg: Option<crate::temporal::TemporalConfig>,
+        file_filter: Option<&str>,
+        symbol_filter: Option<&str>,
+        keyword_weight: f32,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<(u64, f32)>> {
+        if self.query_cache.is_none() {
+            return self.retrieve_with_scores_config_uncached(
+                query,
+                top_k,
+                temporal_cfg,
+                file_filter,
+                symbol_filter,
+                keyword_weight,
+                deadline,
+            );
+        }
+
+        let key = QueryCacheKey {
+            query: query.to_string(),
+            top_k,
+            temporal_config: serde_json::to_string(&temporal_cfg.clone().unwrap_or_default())?,
+            file_filter: file_filter.map(str::to_string),
+            symbol_filter: symbol_filter.map(str::to_string),
+            keyword_weight_bits: keyword_weight.to_bits(),
+            max_candidates_per_note: self.max_candidates_per_note,
+        };
+        let generation = self.generation;
+        if let Some(results) = self.lock_query_cache().and_then(|mut c| c.get(&key, generation)) {
+            return Ok(results);
+        }
+
+        let results = self.retrieve_with_scores_config_uncached(
+            query,
+            top_k,
+            temporal_cfg,
+            file_filter,
+            symbol_filter,
+            keyword_weight,
+            deadline,
+        )?;
+        if let Some(mut cache) = self.lock_query_cache() {
+            cache.insert(key, generation, results.clone());
+        }
+        Ok(results)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn retrieve_with_scores_config_uncached(
+        &self,
+        query: &str,
+        top_k: usize,
+        temporal_cfg: Option<crate::temporal::TemporalConfig>,
+        file_filter: Option<&str>,
+        symbol_filter: Option<&str>,
+        keyword_weight: f32,
+        deadline: Option<Instant>,
+    ) -> Result<Vec<(u64, f32)>> {
+        let candidates = self.retrieve_candidates_until(
+            query,
+            top_k * 4,
+            file_filter,
+            symbol_filter,
+            keyword_weight,
+            deadline,
+        )?;
         let cfg = temporal_cfg.unwrap_or_default();
         
         // --- Spectral Polarity Filtering ---
         let filtered_candidates = if let Some(_spec_emb) = &self.spectral_embeddings {
             // Embed query to get query embedding
-            let _query_emb = crate::embed::get_embedding(query)?;
+            let _query_emb = embedding_cache::query_embedding(query)?;
             
             // Map query to spectral space
             // This is a simplification: for true spectral polarity we need to project 
@@ -907,9 +2159,9 @@ impl SpectralMemoryGraph {
             .notes
             .iter()
             .filter(|(_nid, note)| {
-                // Check if note has any timestamps
+                // Undated notes cannot be placed in the range; the policy decides.
                 if note.source_timestamps.is_empty() {
-                    return false;
+                    return self.missing_timestamp_policy == MissingTimestampPolicy::Include;
                 }
 
                 // Get the earliest timestamp for this note
@@ -963,6 +2215,64 @@ impl SpectralMemoryGraph {
         Ok(results)
     }
 
+    /// Retrieve the top-k `(turn_id, score)` pairs with a fully specified ordering.
+    ///
+    /// This is the stable API for snapshot and regression tests: for the same graph and
+    /// query it returns identical output on every call. Notes are scored in note-id order,
+    /// all sorts use `total_cmp` with id tie-breaks (cluster label, note id, turn id), and
+    /// no step depends on `HashMap` iteration order. Scores are semantic similarity with
+    /// the same top-3 cluster boost as `retrieve_candidates`; temporal re-ranking is not
+    /// applied because it depends on the wall clock.
+    pub fn retrieve_deterministic(&self, query: &str, top_k: usize) -> Result<Vec<(u64, f32)>> {
+        let query_emb = embedding_cache::query_embedding(query)?;
+        let norm_q = query_emb.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+        let mut note_ids: Vec<u32> = self.notes.keys().cloned().collect();
+        note_ids.sort_unstable();
+        let mut scores: Vec<(u32, f32)> = note_ids
+            .iter()
+            .map(|nid| {
+                let note = &self.notes[nid];
+                (*nid, vector_cosine(&note.embedding, note.norm, &query_emb, norm_q))
+            })
+            .collect();
+
+        if let (Some(labels), Some(centroids), Some(centroid_norms)) = (
+            &self.cluster_labels,
+            &self.cluster_centroids,
+            &self.cluster_centroid_norms,
+        ) {
+            if labels.len() == note_ids.len() {
+                let mut cluster_scores: Vec<(usize, f32)> = centroids
+                    .iter()
+                    .map(|(c, vec)| {
+                        let norm_c = centroid_norms.get(c).copied().unwrap_or(0.0);
+                        (*c, vector_cosine(vec, norm_c, &query_emb, norm_q))
+                    })
+                    .collect();
+                cluster_scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+                let top_clusters: Vec<usize> =
+                    cluster_scores.iter().take(3).map(|(c, _)| *c).collect();
+                for (entry, lbl) in scores.iter_mut().zip(labels.iter()) {
+                    if top_clusters.contains(lbl) {
+                        entry.1 *= 1.2;
+                    }
+                }
+            }
+        }
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let mut results: Vec<(u64, f32)> = scores
+            .iter()
+            .take(top_k)
+            .flat_map(|(nid, score)| self.note_candidates(&self.notes[nid], *score))
+            .map(|c| (c.turn_id, c.raw_score))
+            .collect();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        results.truncate(top_k);
+        Ok(results)
+    }
+
     /// Retrieve top-k matching source turn ids for the query string.
     ///
     /// This delegating method calls `retrieve_with_scores` and returns only the
@@ -974,3 +2284,782 @@ impl SpectralMemoryGraph {
         Ok(ids)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(turn_id: u64, content: &str, commit: &str, timestamp: u64) -> ConversationTurn {
+        ConversationTurn {
+            turn_id,
+            speaker: "tester".to_string(),
+            content: content.to_string(),
+            topic: "git".to_string(),
+            entities: Vec::new(),
+            commit_id: Some(commit.to_string()),
+            timestamp,
+            symbol_id: None,
+            ast_node_type: None,
+            file_path: None,
+            segment_index: None,
+            segment_count: None,
+            ingested_at: None,
+        }
+    }
+
+    #[test]
+    fn test_dedup_exact_merges_identical_content() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        smg.ingest_turns_batch_with_dedup(&[turn(1, "fix parser crash", "c1", 100)], None, true)
+            .unwrap();
+        smg.ingest_turns_batch_with_dedup(&[turn(2, "fix parser crash", "c2", 200)], None, true)
+            .unwrap();
+
+        assert_eq!(smg.notes.len(), 1);
+        let note = smg.notes.values().next().unwrap();
+        assert_eq!(note.raw_content, "fix parser crash");
+        assert_eq!(note.source_turn_ids, vec![1, 2]);
+        assert_eq!(
+            note.source_commit_ids,
+            vec![Some("c1".to_string()), Some("c2".to_string())]
+        );
+        assert_eq!(note.source_timestamps, vec![100, 200]);
+
+        // Without dedup the same content produces a separate note.
+        smg.ingest_turns_batch(&[turn(3, "fix parser crash", "c3", 300)], None)
+            .unwrap();
+        assert_eq!(smg.notes.len(), 2);
+    }
+
+    #[test]
+    fn test_pairwise_similarity_is_symmetric_with_unit_diagonal() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for (i, text) in ["add export command", "refactor storage", "fix flaky test"]
+            .iter()
+            .enumerate()
+        {
+            smg.ingest_turn(&turn(i as u64 + 1, text, "c", 0)).unwrap();
+        }
+
+        let ids = [2u32, 0, 1];
+        let sim = smg.pairwise_similarity(&ids);
+        assert_eq!(sim.shape(), &[3, 3]);
+        for i in 0..3 {
+            assert!((sim[(i, i)] - 1.0).abs() < 1e-5);
+            for j in 0..3 {
+                assert!((sim[(i, j)] - sim[(j, i)]).abs() < 1e-6);
+            }
+        }
+    }
+
+    fn note_with_embedding(note_id: u32, embedding: Vec<f32>) -> SMGNote {
+        SMGNote {
+            source_turn_ids: vec![note_id as u64],
+            source_commit_ids: vec![None],
+            source_timestamps: vec![0],
+            ..SMGNote::new(note_id, format!("note {}", note_id), embedding)
+        }
+    }
+
+    #[test]
+    fn test_related_links_fallback_uses_embedding_cosine() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let mut source = note_with_embedding(0, vec![1.0, 0.0, 0.0]);
+        // Hand-edited links carry no score.
+        source.related_note_links = vec![(2, 0.0), (1, 0.0), (99, 0.0)];
+        smg.notes.insert(0, source);
+        smg.notes.insert(1, note_with_embedding(1, vec![0.9, 0.1, 0.0]));
+        smg.notes.insert(2, note_with_embedding(2, vec![0.5, 0.5, 0.5]));
+        assert!(smg.long_range_links.is_none());
+
+        let related = smg.get_related_note_links(0, None);
+        let ids: Vec<u32> = related.iter().map(|(nid, _)| *nid).collect();
+        assert_eq!(ids, vec![1, 2]);
+        assert!(related.iter().all(|(_, score)| *score > 0.0));
+        assert!(related[0].1 > related[1].1);
+
+        let stored = smg.get_related_note_links_with_metric(0, None, RelatednessMetric::StoredScore);
+        assert_eq!(stored.len(), 3);
+        assert!(stored.iter().all(|(_, score)| *score == 0.0));
+    }
+
+    #[test]
+    fn test_routed_retrieval_recall_matches_full_scan() {
+        use crate::graph::spectral::compute_centroids_in_embedding_space;
+
+        // Three well-separated groups: one around the query embedding, two around unrelated texts.
+        let query = "routing query anchor";
+        let anchors = [
+            embed::get_embedding(query).unwrap(),
+            embed::get_embedding("unrelated topic one").unwrap(),
+            embed::get_embedding("unrelated topic two").unwrap(),
+        ];
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let mut labels = Vec::new();
+        for (group, anchor) in anchors.iter().enumerate() {
+            for member in 0..10u32 {
+                let nid = group as u32 * 10 + member;
+                let noise = embed::get_embedding(&format!("noise {}", nid)).unwrap();
+                let embedding: Vec<f32> =
+                    anchor.iter().zip(noise.iter()).map(|(a, n)| a + 0.1 * n).collect();
+                smg.notes.insert(nid, note_with_embedding(nid, embedding));
+                labels.push(group);
+            }
+        }
+        let labels = Array1::from(labels);
+        let mut order: Vec<u32> = smg.notes.keys().cloned().collect();
+        order.sort_unstable();
+        let centroids = compute_centroids_in_embedding_space(&labels, &order, &smg.notes);
+        smg.cluster_centroid_norms = Some(
+            centroids
+                .iter()
+                .map(|(c, v)| (*c, v.iter().map(|x| x * x).sum::<f32>().sqrt()))
+                .collect(),
+        );
+        smg.cluster_centroids = Some(centroids);
+        smg.cluster_labels = Some(labels);
+
+        let k = 5;
+        let full: Vec<u32> = smg
+            .retrieve_candidates(query, k, None, None, 0.0)
+            .unwrap()
+            .iter()
+            .map(|c| c.note_id)
+            .collect();
+        let routed: Vec<u32> = smg
+            .retrieve_candidates_routed(query, k, 1)
+            .unwrap()
+            .iter()
+            .map(|c| c.note_id)
+            .collect();
+
+        assert_eq!(routed.len(), k);
+        assert!(routed.iter().all(|nid| *nid < 10), "routed outside the query cluster");
+        let hits = full.iter().filter(|nid| routed.contains(nid)).count();
+        let recall = hits as f32 / full.len() as f32;
+        assert!(recall >= 0.8, "routed recall {} below threshold", recall);
+
+        // Without clusters the routed path degrades to the full scan.
+        smg.cluster_labels = None;
+        let fallback: Vec<u32> = smg
+            .retrieve_candidates_routed(query, k, 1)
+            .unwrap()
+            .iter()
+            .map(|c| c.note_id)
+            .collect();
+        assert_eq!(fallback.len(), k);
+    }
+
+    #[test]
+    fn test_streaming_batches_never_increase_in_score_on_clustered_graph() {
+        use crate::graph::spectral::compute_centroids_in_embedding_space;
+        use std::sync::mpsc;
+
+        let query = "streaming query anchor";
+        let anchors = [
+            embed::get_embedding("unrelated stream one").unwrap(),
+            embed::get_embedding(query).unwrap(),
+            embed::get_embedding("unrelated stream two").unwrap(),
+        ];
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let mut labels = Vec::new();
+        for (group, anchor) in anchors.iter().enumerate() {
+            for member in 0..10u32 {
+                let nid = group as u32 * 10 + member;
+                let noise = embed::get_embedding(&format!("stream noise {}", nid)).unwrap();
+                let embedding: Vec<f32> =
+                    anchor.iter().zip(noise.iter()).map(|(a, n)| a + 0.1 * n).collect();
+                smg.notes.insert(nid, note_with_embedding(nid, embedding));
+                labels.push(group);
+            }
+        }
+        // An outlier matching the query sits in an unrelated cluster, whose centroid ranks
+        // it last; it must still come first.
+        smg.notes.insert(30, note_with_embedding(30, anchors[1].clone()));
+        labels.push(0);
+        let labels = Array1::from(labels);
+        let order: Vec<u32> = (0..31).collect();
+        let centroids = compute_centroids_in_embedding_space(&labels, &order, &smg.notes);
+        smg.cluster_centroid_norms = Some(
+            centroids
+                .iter()
+                .map(|(c, v)| (*c, v.iter().map(|x| x * x).sum::<f32>().sqrt()))
+                .collect(),
+        );
+        smg.cluster_centroids = Some(centroids);
+        smg.cluster_labels = Some(labels);
+
+        let is_descending = |batch: &[crate::temporal::Candidate]| {
+            batch.windows(2).all(|w| w[0].raw_score >= w[1].raw_score)
+        };
+        let best = 30;
+
+        let (tx, rx) = mpsc::channel();
+        smg.retrieve_candidates_streaming(query, tx).unwrap();
+        // The sender is dropped on return, so draining terminates.
+        let batches: Vec<Vec<crate::temporal::Candidate>> = rx.iter().collect();
+        let flat: Vec<crate::temporal::Candidate> = batches.iter().flatten().cloned().collect();
+        assert!(is_descending(&flat), "scores increased across batches");
+        assert_eq!(flat.len(), 31);
+        assert_eq!(flat[0].note_id, best);
+        // The matching cluster is sent before the unrelated ones are scored.
+        assert!(batches.len() >= 2);
+        assert!((10..20).all(|nid| batches[0].iter().any(|c| c.note_id == nid)));
+
+        // Without clusters all notes fit in one globally ordered batch.
+        smg.cluster_labels = None;
+        let (tx, rx) = mpsc::channel();
+        smg.retrieve_candidates_streaming(query, tx).unwrap();
+        let batches: Vec<Vec<crate::temporal::Candidate>> = rx.iter().collect();
+        assert_eq!(batches.len(), 1);
+        assert!(is_descending(&batches[0]));
+        assert_eq!(batches[0][0].note_id, best);
+
+        // A receiver that hung up stops the scan without an error.
+        let (tx, rx) = mpsc::channel();
+        drop(rx);
+        assert!(smg.retrieve_candidates_streaming(query, tx).is_ok());
+    }
+
+    #[test]
+    fn test_streaming_without_clusters_is_globally_ordered_across_batches() {
+        use std::sync::mpsc;
+
+        let query = "unclustered stream query";
+        let base = embed::get_embedding(query).unwrap();
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let note_count = (STREAM_BATCH_NOTES * 2 + 88) as u32;
+        for nid in 0..note_count {
+            // Scores vary non-monotonically with the note id.
+            let mut embedding = base.clone();
+            embedding[nid as usize % base.len()] += (nid as f32 * 0.37).sin();
+            smg.notes.insert(nid, note_with_embedding(nid, embedding));
+        }
+
+        let (tx, rx) = mpsc::channel();
+        smg.retrieve_candidates_streaming(query, tx).unwrap();
+        let batches: Vec<Vec<crate::temporal::Candidate>> = rx.iter().collect();
+        assert_eq!(
+            batches.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![STREAM_BATCH_NOTES, STREAM_BATCH_NOTES, 88]
+        );
+        let scores: Vec<f32> = batches.iter().flatten().map(|c| c.raw_score).collect();
+        assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn test_deadline_bounded_retrieval_times_out() {
+        let base = embed::get_embedding("deadline query").unwrap();
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let note_count = (DEADLINE_CHUNK_NOTES * 4) as u32;
+        for nid in 0..note_count {
+            let mut embedding = base.clone();
+            embedding[nid as usize % base.len()] += 0.5;
+            smg.notes.insert(nid, note_with_embedding(nid, embedding));
+        }
+        smg.notes.get_mut(&7).unwrap().file_path = Some("src/deadline.rs".to_string());
+        let until = |deadline: Option<Instant>, file_filter: Option<&str>| {
+            smg.retrieve_with_scores_config_until("deadline query", 5, None, file_filter, None, 0.3, deadline)
+        };
+
+        // Warm the query embedding so the deadline is the only thing that can fail.
+        assert!(until(None, None).is_ok());
+
+        let err = until(Some(Instant::now()), None).unwrap_err();
+        let timeout = err.downcast_ref::<RetrievalTimeout>().expect("timeout error");
+        assert!(timeout.notes_scored < note_count as usize);
+
+        let far = Instant::now() + Duration::from_secs(3600);
+        let bounded = until(Some(far), None).unwrap();
+        assert_eq!(bounded, until(None, None).unwrap());
+        assert_eq!(bounded, smg.retrieve_with_scores("deadline query", 5).unwrap());
+        assert!(!bounded.is_empty());
+
+        // Filters apply on the deadline path too.
+        assert_eq!(until(Some(far), Some("src/deadline.rs")).unwrap()[0].0, 7);
+    }
+
+    #[test]
+    fn test_missing_timestamp_policy_controls_undated_notes_in_time_filter() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for nid in 0..6u32 {
+            let mut note =
+                note_with_embedding(nid, embed::get_embedding(&format!("dated note {}", nid)).unwrap());
+            if nid < 3 {
+                note.source_timestamps = vec![1_700_000_000 + nid as u64];
+            } else {
+                // Older graphs may carry no timestamps at all.
+                note.source_timestamps.clear();
+            }
+            smg.notes.insert(nid, note);
+        }
+        let filtered = |smg: &SpectralMemoryGraph| -> Vec<u64> {
+            let mut turns: Vec<u64> = smg
+                .retrieve_with_scores_config_filtered(
+                    "dated note",
+                    10,
+                    None,
+                    Some(1_600_000_000),
+                    None,
+                    None,
+                    None,
+                    0.0,
+                )
+                .unwrap()
+                .into_iter()
+                .map(|(tid, _)| tid)
+                .collect();
+            turns.sort_unstable();
+            turns
+        };
+
+        assert_eq!(smg.missing_timestamp_policy, MissingTimestampPolicy::Exclude);
+        assert_eq!(smg.undated_note_count(), 3);
+        assert_eq!(filtered(&smg), vec![0, 1, 2]);
+
+        smg.missing_timestamp_policy = MissingTimestampPolicy::Include;
+        assert_eq!(filtered(&smg), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_retrieve_deterministic_is_repeatable_with_ties() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let shared = embed::get_embedding("deterministic anchor").unwrap();
+        for nid in 0..20u32 {
+            // Every third note shares an embedding, so scores tie across note ids.
+            let embedding = if nid % 3 == 0 {
+                shared.clone()
+            } else {
+                embed::get_embedding(&format!("deterministic note {}", nid)).unwrap()
+            };
+            let mut note = note_with_embedding(nid, embedding);
+            note.source_turn_ids = vec![nid as u64 * 10, nid as u64 * 10 + 1];
+            note.source_commit_ids = vec![None, None];
+            note.source_timestamps = vec![0, 0];
+            smg.notes.insert(nid, note);
+        }
+        // Two clusters with identical centroids tie on centroid score.
+        smg.cluster_labels = Some(Array1::from((0..20).map(|i| i % 4).collect::<Vec<usize>>()));
+        smg.cluster_centroids = Some((0..4).map(|c| (c, shared.clone())).collect());
+        let norm = shared.iter().map(|x| x * x).sum::<f32>().sqrt();
+        smg.cluster_centroid_norms = Some((0..4).map(|c| (c, norm)).collect());
+
+        let first = smg.retrieve_deterministic("deterministic anchor", 8).unwrap();
+        assert_eq!(first.len(), 8);
+        // Of the tied clusters, 0..=2 are boosted (label order), so shared-embedding notes
+        // 0, 6, 9, 12 and 18 are boosted (3 and 15 sit in cluster 3); the first four fill
+        // the top 8 in id order, each with both turns.
+        let turns: Vec<u64> = first.iter().map(|(tid, _)| *tid).collect();
+        assert_eq!(turns, vec![0, 1, 60, 61, 90, 91, 120, 121]);
+        for _ in 0..100 {
+            assert_eq!(smg.retrieve_deterministic("deterministic anchor", 8).unwrap(), first);
+        }
+    }
+
+    #[test]
+    fn test_cluster_centroid_similarity_is_symmetric_with_unit_diagonal() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        assert!(smg.cluster_centroid_similarity().is_none());
+
+        let mut centroids = HashMap::new();
+        centroids.insert(0, vec![1.0, 0.0, 0.0]);
+        centroids.insert(2, vec![1.0, 1.0, 0.0]);
+        centroids.insert(5, vec![0.0, 0.5, 2.0]);
+        smg.cluster_centroids = Some(centroids);
+
+        let (labels, sim) = smg.cluster_centroid_similarity().unwrap();
+        assert_eq!(labels, vec![0, 2, 5]);
+        for i in 0..labels.len() {
+            assert!((sim[(i, i)] - 1.0).abs() < 1e-6);
+            for j in 0..labels.len() {
+                assert!((sim[(i, j)] - sim[(j, i)]).abs() < 1e-6);
+            }
+        }
+        assert!((sim[(0, 1)] - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+        assert!(sim[(0, 2)].abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_cluster_top_terms_ranks_distinctive_term_first() {
+        let contents = [
+            "fix tokenizer lexer bug in parser",
+            "lexer handles unicode in parser",
+            "refactor lexer error recovery in parser",
+            "add retry to network client in parser",
+            "network timeout handling",
+        ];
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for (i, content) in contents.iter().enumerate() {
+            let mut note = note_with_embedding(i as u32, vec![1.0, 0.0]);
+            note.raw_content = content.to_string();
+            smg.notes.insert(i as u32, note);
+        }
+        smg.next_id = contents.len() as u32;
+        smg.cluster_labels = Some(Array1::from(vec![0, 0, 0, 1, 1]));
+
+        // "lexer" appears in every member of cluster 0 and nowhere else; "parser" is as
+        // frequent but also occurs in cluster 1, so IDF pushes it down.
+        let terms = smg.cluster_top_terms(0, 3);
+        assert_eq!(terms.len(), 3);
+        assert_eq!(terms[0].0, "lexer");
+        assert!(terms[0].1 > terms[1].1);
+        assert!(terms.iter().all(|(t, _)| t != "in"));
+        assert_eq!(smg.cluster_top_terms(1, 1)[0].0, "network");
+        assert!(smg.cluster_top_terms(7, 3).is_empty());
+    }
+
+    #[test]
+    fn test_long_range_link_pages_concatenate_to_full_list() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        assert_eq!(smg.get_long_range_links_page(0, 10), (Vec::new(), None));
+
+        smg.long_range_links = Some(
+            (0..23u32)
+                .map(|i| (i % 5, i, 0.5 + (i % 4) as f32 * 0.1))
+                .collect(),
+        );
+        let full = smg.get_long_range_links(None);
+        smg.sort_long_range_links();
+
+        let mut paged = Vec::new();
+        let mut cursor = Some(0);
+        let mut pages = 0;
+        while let Some(c) = cursor {
+            let (page, next) = smg.get_long_range_links_page(c, 5);
+            assert!(page.len() <= 5);
+            paged.extend(page);
+            cursor = next;
+            pages += 1;
+        }
+        assert_eq!(pages, 5);
+        assert_eq!(paged, full);
+
+        // Past the end yields an empty last page.
+        assert_eq!(smg.get_long_range_links_page(40, 5), (Vec::new(), None));
+    }
+
+    #[test]
+    fn test_link_path_follows_cheapest_chain() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for nid in 0..6u32 {
+            smg.notes.insert(nid, note_with_embedding(nid, vec![1.0, 0.0]));
+        }
+        smg.next_id = 6;
+        // Chain 0-1-2-3 of strong links plus a weak shortcut 0-3; note 4 links to 5 only.
+        smg.long_range_links = Some(vec![
+            (0, 1, 0.9),
+            (2, 1, 0.9),
+            (2, 3, 0.9),
+            (0, 3, 0.2),
+            (4, 5, 0.95),
+        ]);
+
+        assert_eq!(smg.link_path(0, 3), Some(vec![0, 1, 2, 3]));
+        assert_eq!(smg.link_path(3, 0), Some(vec![3, 2, 1, 0]));
+        assert_eq!(smg.link_path(2, 2), Some(vec![2]));
+        assert_eq!(smg.link_path(0, 5), None);
+        assert_eq!(smg.link_path(0, 42), None);
+    }
+
+    #[test]
+    fn test_max_candidates_per_note_caps_turn_expansion() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let mut note = note_with_embedding(0, embed::get_embedding("hot file").unwrap());
+        note.source_turn_ids = (1..=1000).collect();
+        note.source_commit_ids = vec![None; 1000];
+        note.source_timestamps = (1..=1000).map(|t| 1_000 + t).collect();
+        smg.notes.insert(0, note);
+        smg.notes
+            .insert(1, note_with_embedding(1, embed::get_embedding("cold file").unwrap()));
+        smg.next_id = 2;
+
+        let uncapped = smg.retrieve_candidates("hot file", 2, None, None, 0.0).unwrap();
+        assert_eq!(uncapped.iter().filter(|c| c.note_id == 0).count(), 1000);
+
+        smg.max_candidates_per_note = Some(10);
+        let capped = smg.retrieve_candidates("hot file", 2, None, None, 0.0).unwrap();
+        let hot: Vec<u64> = capped
+            .iter()
+            .filter(|c| c.note_id == 0)
+            .map(|c| c.turn_id)
+            .collect();
+        assert_eq!(hot, (991..=1000).collect::<Vec<u64>>());
+        assert_eq!(capped.iter().filter(|c| c.note_id == 1).count(), 1);
+    }
+
+    #[test]
+    fn test_reingest_commit_swaps_commit_notes() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        smg.ingest_turn(&turn(1, "feat: lexer\nfix: parser", "c1", 100))
+            .unwrap();
+        smg.ingest_turn(&turn(2, "docs: readme", "c2", 200)).unwrap();
+        smg.cluster_labels = Some(Array1::from(vec![0, 1]));
+        assert_eq!(smg.notes_for_commit("c1"), vec![0]);
+
+        let resplit = vec![
+            turn(0, "feat: lexer", "c1", 100),
+            turn(0, "fix: parser", "c1", 100),
+        ];
+        let (previous, new_ids) = smg.reingest_commit("c1", &resplit).unwrap();
+        assert_eq!((previous, new_ids), (1, vec![2, 3]));
+        assert_eq!(smg.notes_for_commit("c1"), vec![2, 3]);
+        assert_eq!(smg.notes_for_commit("c2"), vec![1]);
+        assert!(smg.cluster_labels.is_none());
+        // New turns get fresh ids above the existing ones.
+        assert_eq!(smg.notes[&3].source_turn_ids, vec![4]);
+    }
+
+    #[test]
+    fn test_max_links_caps_long_range_links() {
+        use crate::graph::spectral::detect_long_range_links;
+
+        // Two tight groups so every note has strong neighbours and the Laplacian is well defined.
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let anchors = [
+            embed::get_embedding("first topic").unwrap(),
+            embed::get_embedding("second topic").unwrap(),
+        ];
+        for nid in 0..12u32 {
+            let anchor = &anchors[(nid % 2) as usize];
+            let noise = embed::get_embedding(&format!("noise {}", nid)).unwrap();
+            let embedding: Vec<f32> =
+                anchor.iter().zip(noise.iter()).map(|(a, n)| a + 0.2 * n).collect();
+            smg.notes.insert(nid, note_with_embedding(nid, embedding));
+        }
+        smg.next_id = 12;
+
+        // Loose thresholds so far more pairs qualify than the cap allows.
+        let config = SpectralBuildConfig {
+            num_spectral_dims: 4,
+            spectral_link_similarity_threshold: 0.0,
+            embed_link_similarity_threshold: 1.0,
+            max_links: Some(5),
+            ..SpectralBuildConfig::default()
+        };
+        smg.build_spectral_structure_with_config(None, &config).unwrap();
+        let links = smg.long_range_links.clone().unwrap();
+        assert_eq!(links.len(), 5);
+        let persisted = crate::SerializableSMG::from_smg(&smg);
+        assert_eq!(persisted.long_range_links.map(|l| l.len()), Some(5));
+        assert_eq!(persisted.metadata.get("max_links").map(String::as_str), Some("5"));
+
+        // The kept links are the strongest of the uncapped set.
+        let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
+        note_ids.sort_unstable();
+        let all = detect_long_range_links(
+            smg.spectral_embeddings.as_ref().unwrap(),
+            smg.similarity_matrix.as_ref().unwrap(),
+            config.spectral_link_similarity_threshold,
+            config.embed_link_similarity_threshold,
+            &note_ids,
+            &smg.notes,
+            None,
+        );
+        assert!(all.len() > links.len());
+        assert_eq!(links.as_slice(), &all[..links.len()]);
+        let weakest_kept = links.last().unwrap().2;
+        assert!(all[links.len()..].iter().all(|(_, _, s)| *s <= weakest_kept));
+    }
+
+    #[test]
+    fn test_add_turn_assign_cluster_labels_and_retrieves_new_note() {
+        use crate::graph::spectral::compute_centroids_in_embedding_space;
+
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        let anchors = [
+            embed::get_embedding("first topic").unwrap(),
+            embed::get_embedding("second topic").unwrap(),
+        ];
+        for nid in 0..12u32 {
+            let anchor = &anchors[(nid % 2) as usize];
+            let noise = embed::get_embedding(&format!("noise {}", nid)).unwrap();
+            let embedding: Vec<f32> =
+                anchor.iter().zip(noise.iter()).map(|(a, n)| a + 0.2 * n).collect();
+            smg.notes.insert(nid, note_with_embedding(nid, embedding));
+        }
+        smg.next_id = 12;
+        // Cluster state as a build over two well-separated groups would leave it.
+        let order: Vec<u32> = (0..12).collect();
+        let labels = Array1::from((0..12).map(|nid| nid % 2).collect::<Vec<usize>>());
+        let centroids = compute_centroids_in_embedding_space(&labels, &order, &smg.notes);
+        let before = centroids[&0].clone();
+        smg.cluster_centroids = Some(centroids);
+        smg.cluster_labels = Some(labels);
+
+        let label = smg
+            .add_turn_assign_cluster(&turn(500, "first topic", "c500", 1_700_000_500))
+            .unwrap();
+        assert_eq!(label, Some(0));
+        let labels = smg.cluster_labels.as_ref().unwrap();
+        assert_eq!(labels.len(), smg.notes.len());
+        assert_eq!(labels[labels.len() - 1], 0);
+
+        // The centroid moved one seventh of the way towards the new note.
+        let after = &smg.cluster_centroids.as_ref().unwrap()[&0];
+        let expected = before[0] + (anchors[0][0] - before[0]) / 7.0;
+        assert!((after[0] - expected).abs() < 1e-6);
+        assert!(smg.cluster_centroid_norms.as_ref().unwrap().contains_key(&0));
+
+        let results = smg.retrieve("first topic", 1).unwrap();
+        assert_eq!(results.first(), Some(&500));
+    }
+
+    #[test]
+    fn test_query_cache_hits_on_repeated_query() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        smg.ingest_turns_batch(
+            &[
+                turn(1, "parser handles nested blocks", "c1", 1_700_000_001),
+                turn(2, "cache eviction policy", "c2", 1_700_000_002),
+                turn(3, "fix flaky network test", "c3", 1_700_000_003),
+            ],
+            None,
+        )
+        .unwrap();
+        assert!(smg.query_cache_stats().is_none());
+        smg.enable_query_cache(4);
+
+        let cfg = crate::temporal::TemporalConfig {
+            now_seconds: Some(1_700_000_100),
+            ..Default::default()
+        };
+        let first = smg
+            .retrieve_with_scores_config("cache eviction", 2, Some(cfg.clone()), None, None, 0.3)
+            .unwrap();
+        let second = smg
+            .retrieve_with_scores_config("cache eviction", 2, Some(cfg.clone()), None, None, 0.3)
+            .unwrap();
+        assert_eq!(first, second);
+        let stats = smg.query_cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+        // A different config is a separate entry; mutating the graph invalidates entries.
+        smg.retrieve_with_scores_config("cache eviction", 3, Some(cfg.clone()), None, None, 0.3)
+            .unwrap();
+        smg.ingest_turn(&turn(4, "cache warmup on startup", "c4", 1_700_000_004))
+            .unwrap();
+        smg.retrieve_with_scores_config("cache eviction", 2, Some(cfg.clone()), None, None, 0.3)
+            .unwrap();
+        let stats = smg.query_cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (1, 3));
+
+        // Direct field edits are picked up once reported via `mark_modified`.
+        let generation = smg.generation();
+        smg.notes.get_mut(&0).unwrap().file_path = Some("src/cache.rs".to_string());
+        smg.mark_modified();
+        assert_ne!(smg.generation(), generation);
+        smg.retrieve_with_scores_config("cache eviction", 2, Some(cfg), None, None, 0.3)
+            .unwrap();
+        let stats = smg.query_cache_stats().unwrap();
+        assert_eq!((stats.hits, stats.misses), (1, 4));
+    }
+
+    #[test]
+    fn test_retrieve_with_json_config_matches_struct_config() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        smg.ingest_turns_batch(
+            &[
+                turn(1, "parser handles nested blocks", "c1", 1_600_000_000),
+                turn(2, "parser error recovery", "c2", 1_700_000_000),
+                turn(3, "fix flaky network test", "c3", 1_700_000_050),
+            ],
+            None,
+        )
+        .unwrap();
+
+        let cfg = crate::temporal::TemporalConfig {
+            weight: 0.5,
+            mode: crate::temporal::TemporalMode::LinearWindow,
+            window_seconds: Some(86_400),
+            now_seconds: Some(1_700_000_100),
+            ..Default::default()
+        };
+        let cfg_json = r#"{
+            "enabled": true,
+            "weight": 0.5,
+            "mode": "linearwindow",
+            "half_life_seconds": 1209600,
+            "window_seconds": 86400,
+            "boost_magnitude": null,
+            "buckets": null,
+            "now_seconds": 1700000100
+        }"#;
+
+        let from_struct = smg
+            .retrieve_with_scores_config("parser", 3, Some(cfg), None, None, 0.3)
+            .unwrap();
+        let from_json = smg
+            .retrieve_with_scores_json_config("parser", 3, cfg_json)
+            .unwrap();
+        assert_eq!(from_struct, from_json);
+
+        let err = smg
+            .retrieve_with_scores_json_config("parser", 3, "{\"weight\": \"high\"}")
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid temporal config JSON"));
+    }
+
+    #[test]
+    fn test_build_from_block_diagonal_matrix_yields_two_clusters() {
+        let n = 10usize;
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for nid in 0..n as u32 {
+            smg.notes.insert(nid, note_with_embedding(nid, vec![1.0, nid as f32]));
+        }
+        smg.next_id = n as u32;
+
+        // Rows in a shuffled order: even ids form one block, odd ids the other, with a
+        // weak uniform bridge so the graph stays connected.
+        let order: Vec<u32> = (0..n as u32).map(|i| (i * 7) % n as u32).collect();
+        let sim = Array2::from_shape_fn((n, n), |(i, j)| {
+            if order[i] % 2 == order[j] % 2 {
+                0.9
+            } else {
+                0.3
+            }
+        });
+
+        assert!(smg
+            .build_spectral_structure_from_matrix(Array2::zeros((n - 1, n - 1)), &order[..n - 1])
+            .is_err());
+        let mut dup = order.clone();
+        dup[1] = dup[0];
+        assert!(smg
+            .build_spectral_structure_from_matrix(sim.clone(), &dup)
+            .is_err());
+        // A rejected config is not recorded as the last build config.
+        let invalid = SpectralBuildConfig {
+            min_clusters: 0,
+            ..SpectralBuildConfig::default()
+        };
+        assert!(smg
+            .build_spectral_structure_from_matrix_with_config(sim.clone(), &order, None, &invalid)
+            .is_err());
+        assert!(smg.last_build_config.is_none());
+
+        // Three dims: the constant vector, the block indicator and one bulk vector.
+        let config = SpectralBuildConfig {
+            num_spectral_dims: 3,
+            min_clusters: 2,
+            max_clusters: 2,
+            ..SpectralBuildConfig::default()
+        };
+        smg.build_spectral_structure_from_matrix_with_config(sim, &order, None, &config)
+            .unwrap();
+
+        // Labels are indexed by sorted note id: evens share one label, odds the other.
+        let labels = smg.cluster_labels.as_ref().unwrap();
+        assert_eq!(labels.len(), n);
+        for nid in 0..n {
+            assert_eq!(labels[nid] == labels[0], nid % 2 == 0, "labels {:?}", labels);
+        }
+
+        // Rebuilding a graph shrunk below three notes clears the earlier structures.
+        smg.notes.retain(|nid, _| *nid < 2);
+        smg.build_spectral_structure_from_matrix(Array2::eye(2), &[0, 1]).unwrap();
+        assert!(smg.cluster_labels.is_none());
+        assert!(smg.cluster_centroids.is_none());
+        assert!(smg.similarity_matrix.is_none());
+        assert!(smg.long_range_links.is_none());
+    }
+}
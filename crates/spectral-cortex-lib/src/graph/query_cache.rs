@@ -0,0 +1,112 @@
+//! Size-bounded LRU cache for retrieval results.
+//!
+//! Entries are keyed by the query and the effective retrieval configuration (compared in
+//! full, not by digest), and tagged with the graph generation they were computed against.
+//! A lookup against a different generation is a miss, so results never outlive the graph
+//! state that produced them.
+
+use std::collections::{HashMap, VecDeque};
+
+/// Hit/miss counters and current size of a `QueryCache`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QueryCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// Everything that determines a retrieval result besides the graph itself.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QueryCacheKey {
+    pub query: String,
+    pub top_k: usize,
+    /// The effective `TemporalConfig`, serialized as JSON.
+    pub temporal_config: String,
+    pub file_filter: Option<String>,
+    pub symbol_filter: Option<String>,
+    /// `keyword_weight.to_bits()`, so the key stays `Eq`.
+    pub keyword_weight_bits: u32,
+    pub max_candidates_per_note: Option<usize>,
+}
+
+struct CacheEntry {
+    generation: u64,
+    results: Vec<(u64, f32)>,
+}
+
+/// LRU cache mapping a query key to ranked `(turn_id, score)` results.
+pub struct QueryCache {
+    capacity: usize,
+    entries: HashMap<QueryCacheKey, CacheEntry>,
+    /// Keys from least to most recently used.
+    order: VecDeque<QueryCacheKey>,
+    hits: u64,
+    misses: u64,
+}
+
+impl QueryCache {
+    /// Create a cache holding at most `capacity` entries (at least one).
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Look up `key`, counting a hit only if it was stored against `generation`.
+    pub fn get(&mut self, key: &QueryCacheKey, generation: u64) -> Option<Vec<(u64, f32)>> {
+        match self.entries.get(key) {
+            Some(entry) if entry.generation == generation => {
+                let results = entry.results.clone();
+                self.touch(key);
+                self.hits += 1;
+                Some(results)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Store results for `key`, evicting the least recently used entry when full.
+    pub fn insert(&mut self, key: QueryCacheKey, generation: u64, results: Vec<(u64, f32)>) {
+        let entry = CacheEntry { generation, results };
+        if self.entries.insert(key.clone(), entry).is_some() {
+            self.touch(&key);
+            return;
+        }
+        self.order.push_back(key);
+        while self.order.len() > self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+    }
+
+    /// Drop all entries; counters are kept.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    /// Current counters and size.
+    pub fn stats(&self) -> QueryCacheStats {
+        QueryCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+        }
+    }
+
+    fn touch(&mut self, key: &QueryCacheKey) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            if let Some(k) = self.order.remove(pos) {
+                self.order.push_back(k);
+            }
+        }
+    }
+}
@@ -60,6 +60,49 @@ pub fn assemble_embedding_matrix(notes: &HashMap<u32, SMGNote>, order: &[u32]) -
     mat
 }
 
+/// Like `assemble_embedding_matrix`, but refuses to allocate more than `max_bytes`.
+///
+/// The `n × d` f32 allocation is estimated up front so that oversized graphs fail with
+/// a descriptive error instead of aborting on allocation.
+///
+/// # Arguments
+///
+/// * `notes` - map from note id to `SMGNote`
+/// * `order` - vector of note ids specifying the row order in the returned matrix
+/// * `max_bytes` - allocation budget in bytes; `None` disables the guard
+///
+/// # Returns
+///
+/// The assembled `(n, d)` matrix.
+///
+/// # Errors
+///
+/// Returns an error if the estimated allocation exceeds `max_bytes`.
+pub fn assemble_embedding_matrix_with_budget(
+    notes: &HashMap<u32, SMGNote>,
+    order: &[u32],
+    max_bytes: Option<usize>,
+) -> Result<Array2<f32>> {
+    if let (Some(budget), Some(first)) = (max_bytes, order.first()) {
+        let n = order.len();
+        let d = notes[first].embedding.len();
+        let needed = n
+            .checked_mul(d)
+            .and_then(|cells| cells.checked_mul(std::mem::size_of::<f32>()));
+        if !matches!(needed, Some(bytes) if bytes <= budget) {
+            return Err(anyhow::anyhow!(
+                "embedding matrix for {} notes x {} dims needs {} bytes, exceeding the budget of {} bytes; \
+                 split the graph into smaller shards or use a sparse similarity path",
+                n,
+                d,
+                needed.map_or_else(|| "more than usize::MAX".to_string(), |b| b.to_string()),
+                budget
+            ));
+        }
+    }
+    Ok(assemble_embedding_matrix(notes, order))
+}
+
 /// Compute pairwise similarity matrix fusing semantic similarity with structural links.
 ///
 /// # Arguments
@@ -537,11 +580,38 @@ pub fn compute_spectral_embeddings(
 ///
 /// Returns an error if the clustering algorithm fails.
 pub fn run_kmeans_on_spectral(spec: &Array2<f32>, n_clusters: usize) -> Result<Array1<usize>> {
+    run_kmeans_on_spectral_with_params(spec, n_clusters, 100, 1e-4)
+}
+
+/// Run K‑Means on the spectral embeddings with explicit stop conditions.
+///
+/// # Arguments
+///
+/// * `spec` - spectral embeddings matrix (n × k)
+/// * `n_clusters` - requested number of clusters
+/// * `max_iters` - maximum number of Lloyd iterations per run
+/// * `tolerance` - stop once the centroids move less than this between iterations
+///
+/// # Returns
+///
+/// `Array1<usize>` containing a label per row.
+///
+/// # Errors
+///
+/// Returns an error if the clustering algorithm fails or the parameters are invalid
+/// (e.g. `max_iters == 0` or a non-positive `tolerance`).
+pub fn run_kmeans_on_spectral_with_params(
+    spec: &Array2<f32>,
+    n_clusters: usize,
+    max_iters: u64,
+    tolerance: f32,
+) -> Result<Array1<usize>> {
     // Provide an empty target array to satisfy Dataset typing.
     let targets = Array1::<usize>::zeros(0);
     let dataset = linfa::Dataset::new(spec.clone(), targets);
     let kmeans = KMeans::params(n_clusters)
-        .max_n_iterations(100)
+        .max_n_iterations(max_iters)
+        .tolerance(tolerance)
         .fit(&dataset)?;
     let labels = kmeans.predict(&dataset);
     Ok(labels)
@@ -14,16 +14,20 @@ pub mod temporal;
 pub mod utils;
 
 // Re‑export primary types for ergonomic use.
-pub use graph::{SpectralBuildConfig, SpectralMemoryGraph};
+pub use graph::{
+    MissingTimestampPolicy, RelatednessMetric, RetrievalTimeout, SpectralBuildConfig,
+    SpectralMemoryGraph,
+};
 pub use model::{conversation_turn::ConversationTurn, smg_note::SMGNote};
 
 use anyhow::Result;
+use nalgebra_sparse::{CooMatrix, CsrMatrix};
 use ndarray::Array1;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
-use std::path::Path;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
 // use std::time::Instant;
 
 /// Serializable representation of a single note stored in the SMG.
@@ -62,13 +66,24 @@ pub struct SerializableNote {
     pub file_path: Option<String>,
     /// Structural link neighbors (note_ids).
     pub structural_links: Vec<u32>,
+    /// Position of the commit segment this note came from (0-based), if split.
+    #[serde(default)]
+    pub segment_index: Option<usize>,
+    /// Number of segments the source commit was split into.
+    #[serde(default)]
+    pub segment_count: Option<usize>,
+    /// Ingest times (unix epoch seconds) parallel to `source_turn_ids`; empty for
+    /// graphs written before ingest times were recorded.
+    #[serde(default)]
+    pub source_ingested_at: Vec<u64>,
 }
 
 /// Top-level serialisable SMG container.
 ///
-/// `similarity_matrix` and `spectral_embeddings` are omitted because they are
-/// large and can be recomputed from embeddings; we persist cluster information
-/// and centroids to speed up query-time boosts.
+/// `spectral_embeddings` are omitted because they are large and can be recomputed
+/// from embeddings; we persist cluster information and centroids to speed up
+/// query-time boosts. The similarity matrix is only persisted on request (see
+/// `SmgSaveOptions`).
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SerializableSMG {
     pub metadata: HashMap<String, String>,
@@ -77,10 +92,26 @@ pub struct SerializableSMG {
     pub cluster_centroids: Option<HashMap<usize, Vec<f32>>>,
     pub cluster_centroid_norms: Option<HashMap<usize, f32>>,
     pub long_range_links: Option<Vec<(u32, u32, f32)>>,
+    /// Sparsified similarity matrix as `(note_id_a, note_id_b, weight)` triplets.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity_triplets: Option<Vec<(u32, u32, f32)>>,
+}
+
+/// Options for `save_smg_json_with_options`.
+#[derive(Clone, Debug, Default)]
+pub struct SmgSaveOptions {
+    /// Persist the sparsified similarity matrix so loading restores
+    /// `similarity_matrix` without recomputing cosine similarities. Off by default
+    /// to keep files compact.
+    pub include_similarity_matrix: bool,
 }
 
 impl SerializableSMG {
     pub fn from_smg(smg: &SpectralMemoryGraph) -> Self {
+        Self::from_smg_with_options(smg, &SmgSaveOptions::default())
+    }
+
+    pub fn from_smg_with_options(smg: &SpectralMemoryGraph, options: &SmgSaveOptions) -> Self {
         // Prepare serialisable notes in stable order (sort by note_id).
         let mut notes: Vec<SerializableNote> =
             smg.notes.values().map(SerializableNote::from).collect();
@@ -103,6 +134,11 @@ impl SerializableSMG {
             metadata.insert("num_spectral_dims".to_string(), config.num_spectral_dims.to_string());
             metadata.insert("min_clusters".to_string(), config.min_clusters.to_string());
             metadata.insert("max_clusters".to_string(), config.max_clusters.to_string());
+            if let Some(max_links) = config.max_links {
+                metadata.insert("max_links".to_string(), max_links.to_string());
+            }
+            metadata.insert("kmeans_max_iters".to_string(), config.kmeans_max_iters.to_string());
+            metadata.insert("kmeans_tolerance".to_string(), config.kmeans_tolerance.to_string());
         }
 
         Self {
@@ -112,10 +148,65 @@ impl SerializableSMG {
             cluster_centroids,
             cluster_centroid_norms: smg.cluster_centroid_norms.clone(),
             long_range_links: smg.long_range_links.clone(),
+            similarity_triplets: if options.include_similarity_matrix {
+                similarity_triplets(smg)
+            } else {
+                None
+            },
         }
     }
 }
 
+/// Convert `smg.similarity_matrix` into note-id triplets via `similarity_note_ids`.
+///
+/// Returns `None` when there is no matrix or the notes it was built over are no
+/// longer exactly the current notes (e.g. notes were added or replaced after the
+/// last build).
+fn similarity_triplets(smg: &SpectralMemoryGraph) -> Option<Vec<(u32, u32, f32)>> {
+    let sim = smg.similarity_matrix.as_ref()?;
+    let note_ids = smg.similarity_note_ids.as_ref()?;
+    let n = note_ids.len();
+    if sim.nrows() != n || sim.ncols() != n || n != smg.notes.len() {
+        return None;
+    }
+    if !note_ids.iter().all(|nid| smg.notes.contains_key(nid)) {
+        return None;
+    }
+    Some(
+        sim.triplet_iter()
+            .map(|(i, j, w)| (note_ids[i], note_ids[j], *w))
+            .collect(),
+    )
+}
+
+/// Rebuild a CSR similarity matrix over the sorted note ids from note-id triplets.
+fn similarity_from_triplets(
+    smg: &SpectralMemoryGraph,
+    triplets: &[(u32, u32, f32)],
+) -> Result<CsrMatrix<f32>> {
+    let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
+    note_ids.sort_unstable();
+    let index: HashMap<u32, usize> = note_ids.iter().enumerate().map(|(i, nid)| (*nid, i)).collect();
+
+    let n = note_ids.len();
+    let (mut rows, mut cols, mut values) = (Vec::new(), Vec::new(), Vec::new());
+    for (a, b, w) in triplets {
+        let (Some(&i), Some(&j)) = (index.get(a), index.get(b)) else {
+            return Err(anyhow::anyhow!(
+                "similarity triplet ({}, {}) references an unknown note id",
+                a,
+                b
+            ));
+        };
+        rows.push(i);
+        cols.push(j);
+        values.push(*w);
+    }
+    let coo = CooMatrix::try_from_triplets(n, n, rows, cols, values)
+        .map_err(|e| anyhow::anyhow!("invalid similarity triplets: {}", e))?;
+    Ok(CsrMatrix::from(&coo))
+}
+
 impl From<&SMGNote> for SerializableNote {
     fn from(n: &SMGNote) -> Self {
         // Convert internal SMGNote into a serialisable form.
@@ -132,19 +223,48 @@ impl From<&SMGNote> for SerializableNote {
             ast_node_type: n.ast_node_type.clone(),
             file_path: n.file_path.clone(),
             structural_links: n.structural_links.clone(),
+            segment_index: n.segment_index,
+            segment_count: n.segment_count,
+            source_ingested_at: n.source_ingested_at.clone(),
         }
     }
 }
 
 /// Save the provided `SpectralMemoryGraph` to a JSON file.
 pub fn save_smg_json(smg: &SpectralMemoryGraph, path: &Path) -> Result<()> {
-    let serial = SerializableSMG::from_smg(smg);
+    save_smg_json_with_options(smg, path, &SmgSaveOptions::default())
+}
+
+/// Save the provided `SpectralMemoryGraph` to a JSON file with explicit options.
+///
+/// With `include_similarity_matrix`, the sparsified similarity matrix from the last
+/// build is written as triplets and `load_smg_json` restores it into
+/// `similarity_matrix`. It is skipped if the matrix is stale (notes changed since the build).
+pub fn save_smg_json_with_options(
+    smg: &SpectralMemoryGraph,
+    path: &Path,
+    options: &SmgSaveOptions,
+) -> Result<()> {
+    let serial = SerializableSMG::from_smg_with_options(smg, options);
     let file = File::create(path)?;
     let writer = BufWriter::new(file);
     serde_json::to_writer(writer, &serial)?;
     Ok(())
 }
 
+/// Save only the notes whose cluster label is in `clusters` (a filtered export).
+///
+/// Links are kept only among the retained notes; see
+/// `SpectralMemoryGraph::subgraph_for_clusters`. The file loads with `load_smg_json`.
+///
+/// # Errors
+///
+/// Returns an error if the graph has no cluster labels or the file cannot be written.
+pub fn save_smg_json_clusters(smg: &SpectralMemoryGraph, path: &Path, clusters: &[usize]) -> Result<()> {
+    let sub = smg.subgraph_for_clusters(clusters)?;
+    save_smg_json(&sub, path)
+}
+
 /// Load an SMG from a JSON file previously written with `save_smg_json`.
 pub fn load_smg_json(path: &Path) -> Result<SpectralMemoryGraph> {
     let file = BufReader::new(File::open(path)?);
@@ -152,6 +272,207 @@ pub fn load_smg_json(path: &Path) -> Result<SpectralMemoryGraph> {
     validate_serial_smg(serial)
 }
 
+/// Write the note → cluster assignment as CSV (`note_id,cluster_label`) to `path`.
+///
+/// Rows follow ascending `note_id`, the order `cluster_labels` is indexed by. If the
+/// graph has no cluster labels (or they do not cover every note) only the header is
+/// written.
+///
+/// # Returns
+///
+/// The number of data rows written: `0` for a non-empty graph means it had no usable
+/// cluster labels.
+pub fn export_labels_csv(smg: &SpectralMemoryGraph, path: &Path) -> Result<usize> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "note_id,cluster_label")?;
+
+    let labels = match &smg.cluster_labels {
+        Some(labels) if labels.len() == smg.notes.len() => labels,
+        _ => {
+            writer.flush()?;
+            return Ok(0);
+        }
+    };
+
+    let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
+    note_ids.sort_unstable();
+    for (nid, label) in note_ids.iter().zip(labels.iter()) {
+        writeln!(writer, "{},{}", nid, label)?;
+    }
+    writer.flush()?;
+    Ok(note_ids.len())
+}
+
+/// Default number of notes written to each shard by `save_smg_dir`.
+pub const DEFAULT_NOTES_PER_SHARD: usize = 10_000;
+
+/// Manifest of a directory-format SMG written by `save_smg_dir`.
+///
+/// Holds everything except the notes themselves; notes live in the shard files
+/// listed in `shards` (relative to the directory), in ascending `note_id` order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SmgDirManifest {
+    /// Format version and build configuration, as in the single-file format.
+    pub metadata: HashMap<String, String>,
+    /// Shard file names in load order.
+    pub shards: Vec<String>,
+    /// Total number of notes across all shards.
+    pub note_count: usize,
+    /// Cluster label per note, indexed by ascending `note_id`.
+    pub cluster_labels: Option<Vec<usize>>,
+    /// Mean embedding per cluster label.
+    pub cluster_centroids: Option<HashMap<usize, Vec<f32>>>,
+    /// L2 norm of each cluster centroid.
+    pub cluster_centroid_norms: Option<HashMap<usize, f32>>,
+    /// `(note_id_a, note_id_b, spectral_similarity)` long-range links.
+    pub long_range_links: Option<Vec<(u32, u32, f32)>>,
+    /// Sparsified similarity matrix, when saved with `include_similarity_matrix`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity_triplets: Option<Vec<(u32, u32, f32)>>,
+}
+
+/// Save the provided `SpectralMemoryGraph` to a directory of sharded JSON files.
+///
+/// Uses `DEFAULT_NOTES_PER_SHARD`; see `save_smg_dir_with_shard_size`.
+pub fn save_smg_dir(smg: &SpectralMemoryGraph, dir: &Path) -> Result<usize> {
+    save_smg_dir_with_shard_size(smg, dir, DEFAULT_NOTES_PER_SHARD)
+}
+
+/// Save the provided `SpectralMemoryGraph` to `dir` as `notes_0000.json`, ... plus `manifest.json`.
+///
+/// Each file is written to a temporary name, synced and renamed into place, and the
+/// manifest is written last, so an interrupted save never leaves a directory that loads
+/// as complete. Shards whose file already holds identical content are not rewritten, so
+/// re-running an interrupted save (or saving a graph that changed in a few notes) only
+/// writes the shards that differ.
+///
+/// # Returns
+///
+/// The number of shard files written.
+///
+/// # Errors
+///
+/// Returns an error if `notes_per_shard` is zero or any file cannot be written.
+pub fn save_smg_dir_with_shard_size(
+    smg: &SpectralMemoryGraph,
+    dir: &Path,
+    notes_per_shard: usize,
+) -> Result<usize> {
+    save_smg_dir_with_options(smg, dir, notes_per_shard, &SmgSaveOptions::default())
+}
+
+/// `save_smg_dir_with_shard_size` with explicit options.
+///
+/// With `include_similarity_matrix`, the similarity triplets are stored in the
+/// manifest and `load_smg_dir` restores them, as with `save_smg_json_with_options`.
+pub fn save_smg_dir_with_options(
+    smg: &SpectralMemoryGraph,
+    dir: &Path,
+    notes_per_shard: usize,
+    options: &SmgSaveOptions,
+) -> Result<usize> {
+    if notes_per_shard == 0 {
+        return Err(anyhow::anyhow!("notes_per_shard must be at least 1"));
+    }
+    std::fs::create_dir_all(dir)?;
+
+    let serial = SerializableSMG::from_smg_with_options(smg, options);
+    let mut shards = Vec::new();
+    let mut written = 0;
+    for (i, chunk) in serial.notes.chunks(notes_per_shard).enumerate() {
+        let name = format!("notes_{:04}.json", i);
+        let path = dir.join(&name);
+        let bytes = serde_json::to_vec(chunk)?;
+        if std::fs::read(&path).ok().as_deref() != Some(bytes.as_slice()) {
+            write_bytes_atomic(&path, &bytes)?;
+            written += 1;
+        }
+        shards.push(name);
+    }
+
+    let manifest = SmgDirManifest {
+        metadata: serial.metadata,
+        shards,
+        note_count: serial.notes.len(),
+        cluster_labels: serial.cluster_labels,
+        cluster_centroids: serial.cluster_centroids,
+        cluster_centroid_norms: serial.cluster_centroid_norms,
+        long_range_links: serial.long_range_links,
+        similarity_triplets: serial.similarity_triplets,
+    };
+    write_json_atomic(&dir.join("manifest.json"), &manifest)?;
+    Ok(written)
+}
+
+/// Load an SMG from a directory previously written with `save_smg_dir`.
+///
+/// Shards are read in parallel and validated against the manifest's note count.
+pub fn load_smg_dir(dir: &Path) -> Result<SpectralMemoryGraph> {
+    use rayon::prelude::*;
+
+    let manifest: SmgDirManifest =
+        serde_json::from_reader(BufReader::new(File::open(dir.join("manifest.json"))?))?;
+
+    let shards: Vec<Vec<SerializableNote>> = manifest
+        .shards
+        .par_iter()
+        .map(|name| -> Result<Vec<SerializableNote>> {
+            let path = dir.join(name);
+            let file = File::open(&path)
+                .map_err(|e| anyhow::anyhow!("failed to open shard {}: {}", path.display(), e))?;
+            Ok(serde_json::from_reader(BufReader::new(file))?)
+        })
+        .collect::<Result<_>>()?;
+    let notes: Vec<SerializableNote> = shards.into_iter().flatten().collect();
+    if notes.len() != manifest.note_count {
+        return Err(anyhow::anyhow!(
+            "SMG directory {} is incomplete: manifest lists {} notes, shards contain {}",
+            dir.display(),
+            manifest.note_count,
+            notes.len()
+        ));
+    }
+
+    validate_serial_smg(SerializableSMG {
+        metadata: manifest.metadata,
+        notes,
+        cluster_labels: manifest.cluster_labels,
+        cluster_centroids: manifest.cluster_centroids,
+        cluster_centroid_norms: manifest.cluster_centroid_norms,
+        long_range_links: manifest.long_range_links,
+        similarity_triplets: manifest.similarity_triplets,
+    })
+}
+
+/// Temporary sibling path that `path` is written to before being renamed into place.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut tmp = PathBuf::from(path);
+    tmp.set_extension("json.tmp");
+    tmp
+}
+
+/// Serialize `value` to `path` via a synced temporary file, so a failed write never
+/// replaces an existing file.
+fn write_json_atomic<T: Serialize + ?Sized>(path: &Path, value: &T) -> Result<()> {
+    let tmp = temp_path(path);
+    let mut writer = BufWriter::new(File::create(&tmp)?);
+    serde_json::to_writer(&mut writer, value)?;
+    writer.flush()?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Write pre-serialized `bytes` to `path` via a synced temporary file.
+fn write_bytes_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let tmp = temp_path(path);
+    let mut file = File::create(&tmp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
 fn validate_serial_smg(serial: SerializableSMG) -> Result<SpectralMemoryGraph> {
     let format_version = serial
         .metadata
@@ -189,6 +510,24 @@ fn validate_serial_smg(serial: SerializableSMG) -> Result<SpectralMemoryGraph> {
             has_config = true;
         }
     }
+    if let Some(val) = serial.metadata.get("max_links") {
+        if let Ok(n) = val.parse::<usize>() {
+            config.max_links = Some(n);
+            has_config = true;
+        }
+    }
+    if let Some(val) = serial.metadata.get("kmeans_max_iters") {
+        if let Ok(n) = val.parse::<u64>() {
+            config.kmeans_max_iters = n;
+            has_config = true;
+        }
+    }
+    if let Some(val) = serial.metadata.get("kmeans_tolerance") {
+        if let Ok(t) = val.parse::<f32>() {
+            config.kmeans_tolerance = t;
+            has_config = true;
+        }
+    }
     if has_config {
         smg.last_build_config = Some(config);
     }
@@ -211,6 +550,9 @@ fn validate_serial_smg(serial: SerializableSMG) -> Result<SpectralMemoryGraph> {
             ast_node_type: sn.ast_node_type,
             file_path: sn.file_path,
             structural_links: sn.structural_links,
+            segment_index: sn.segment_index,
+            segment_count: sn.segment_count,
+            source_ingested_at: sn.source_ingested_at,
         };
         smg.notes.insert(nid, note);
         // Keep next_id ahead of the highest assembled note id.
@@ -228,14 +570,255 @@ fn validate_serial_smg(serial: SerializableSMG) -> Result<SpectralMemoryGraph> {
     // Restore centroid norms if present.
     smg.cluster_centroid_norms = serial.cluster_centroid_norms;
 
-    // similarity_matrix and spectral_embeddings are intentionally left None to avoid
-    // storing very large matrices; callers should call `build_spectral_structure`
-    // if they need a fully-built SMG.
-    smg.similarity_matrix = None;
+    // spectral_embeddings are intentionally left None to avoid storing very large
+    // matrices; callers should call `build_spectral_structure` if they need a
+    // fully-built SMG. The similarity matrix is restored only if it was persisted.
+    smg.similarity_matrix = match &serial.similarity_triplets {
+        Some(triplets) => Some(similarity_from_triplets(&smg, triplets)?),
+        None => None,
+    };
+    // `similarity_from_triplets` indexes rows by ascending note id.
+    smg.similarity_note_ids = smg.similarity_matrix.as_ref().map(|_| {
+        let mut note_ids: Vec<u32> = smg.notes.keys().cloned().collect();
+        note_ids.sort_unstable();
+        note_ids
+    });
     smg.spectral_embeddings = None;
 
-    // Restore long-range links if present.
+    // Restore long-range links if present, in the order link paging relies on.
     smg.long_range_links = serial.long_range_links;
+    smg.sort_long_range_links();
 
     Ok(smg)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A git turn with no commit or file metadata, timestamped `1_700_000_000 + turn_id`.
+    fn turn(turn_id: u64, content: impl Into<String>) -> ConversationTurn {
+        ConversationTurn {
+            turn_id,
+            speaker: "git".to_string(),
+            content: content.into(),
+            topic: "git".to_string(),
+            entities: vec![],
+            commit_id: None,
+            timestamp: 1_700_000_000 + turn_id,
+            symbol_id: None,
+            ast_node_type: None,
+            file_path: None,
+            segment_index: None,
+            segment_count: None,
+            ingested_at: None,
+        }
+    }
+
+    /// A fresh path under the temp dir, unique per call.
+    fn scratch_path(prefix: &str) -> PathBuf {
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        std::env::temp_dir().join(format!("spectral_cortex_{}_{}", prefix, stamp))
+    }
+
+    #[test]
+    fn test_dir_format_roundtrip_matches_single_file() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for i in 0..7u64 {
+            let turn = ConversationTurn {
+                commit_id: Some(format!("c{}", i)),
+                ..turn(i, format!("commit message number {}", i))
+            };
+            smg.ingest_turn(&turn).unwrap();
+        }
+        smg.cluster_labels = Some(Array1::from(vec![0, 0, 0, 1, 1, 1, 1]));
+        smg.long_range_links = Some(vec![(0, 4, 0.5)]);
+
+        let base = scratch_path("dir_test");
+        let json_path = base.with_extension("json");
+        save_smg_json(&smg, &json_path).unwrap();
+        assert_eq!(save_smg_dir_with_shard_size(&smg, &base, 3).unwrap(), 3);
+        assert!(base.join("notes_0002.json").exists());
+
+        // Re-saving only rewrites shards whose notes changed.
+        assert_eq!(save_smg_dir_with_shard_size(&smg, &base, 3).unwrap(), 0);
+        let mut edited = load_smg_dir(&base).unwrap();
+        edited.notes.get_mut(&4).unwrap().raw_content.push_str(" (edited)");
+        assert_eq!(save_smg_dir_with_shard_size(&edited, &base, 3).unwrap(), 1);
+        assert_eq!(save_smg_dir_with_shard_size(&smg, &base, 3).unwrap(), 1);
+
+        let from_json = load_smg_json(&json_path).unwrap();
+        let from_dir = load_smg_dir(&base).unwrap();
+        assert_eq!(from_dir.notes.len(), smg.notes.len());
+        assert_eq!(from_dir.cluster_labels, from_json.cluster_labels);
+        assert_eq!(from_dir.long_range_links, from_json.long_range_links);
+        assert_eq!(
+            from_dir.retrieve("commit message number 3", 3).unwrap(),
+            from_json.retrieve("commit message number 3", 3).unwrap()
+        );
+
+        // A missing shard is reported instead of silently loading a partial graph.
+        std::fs::remove_file(base.join("notes_0001.json")).unwrap();
+        assert!(load_smg_dir(&base).is_err());
+
+        let _ = std::fs::remove_file(&json_path);
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_export_labels_csv_matches_cluster_labels() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for i in 0..4u64 {
+            smg.ingest_turn(&turn(i, format!("labelled note {}", i))).unwrap();
+        }
+        let path = scratch_path("labels").with_extension("csv");
+
+        // Without clusters only the header is written.
+        assert_eq!(export_labels_csv(&smg, &path).unwrap(), 0);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "note_id,cluster_label\n");
+
+        smg.cluster_labels = Some(Array1::from(vec![2, 0, 2, 1]));
+        assert_eq!(export_labels_csv(&smg, &path).unwrap(), smg.notes.len());
+        let csv = std::fs::read_to_string(&path).unwrap();
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+        assert_eq!(rows.len(), smg.notes.len());
+        assert_eq!(rows, vec!["0,2", "1,0", "2,2", "3,1"]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_similarity_matrix_roundtrip_is_opt_in() {
+        let n = 10usize;
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for i in 0..n as u64 {
+            smg.ingest_turn(&turn(i, format!("similarity note {}", i))).unwrap();
+        }
+        // Two blocks (even/odd ids) joined by a weaker bridge; rows in shuffled order.
+        let order: Vec<u32> = (0..n as u32).map(|i| (i * 7) % n as u32).collect();
+        let sim = ndarray::Array2::from_shape_fn((n, n), |(i, j)| {
+            if order[i] % 2 == order[j] % 2 {
+                0.9
+            } else {
+                0.3
+            }
+        });
+        let config = SpectralBuildConfig {
+            num_spectral_dims: 3,
+            min_clusters: 2,
+            max_clusters: 2,
+            ..SpectralBuildConfig::default()
+        };
+        smg.build_spectral_structure_from_matrix_with_config(sim, &order, None, &config)
+            .unwrap();
+        let built = smg.similarity_matrix.clone().unwrap();
+        assert!(built.nnz() > 0);
+
+        let plain = scratch_path("sim_plain").with_extension("json");
+        let with_sim = scratch_path("sim_full").with_extension("json");
+
+        save_smg_json(&smg, &plain).unwrap();
+        assert!(load_smg_json(&plain).unwrap().similarity_matrix.is_none());
+
+        let options = SmgSaveOptions {
+            include_similarity_matrix: true,
+        };
+        save_smg_json_with_options(&smg, &with_sim, &options).unwrap();
+        let restored = load_smg_json(&with_sim).unwrap();
+        assert_eq!(restored.similarity_matrix.as_ref(), Some(&built));
+
+        // The directory format restores it too.
+        let dir = scratch_path("sim_dir");
+        save_smg_dir_with_options(&smg, &dir, 4, &options).unwrap();
+        assert_eq!(load_smg_dir(&dir).unwrap().similarity_matrix.as_ref(), Some(&built));
+
+        // Same note count but a different note set: the matrix is stale and skipped.
+        smg.notes.remove(&(n as u32 - 1));
+        smg.ingest_turn(&turn(n as u64, "replacement note")).unwrap();
+        assert_eq!(smg.notes.len(), n);
+        save_smg_json_with_options(&smg, &with_sim, &options).unwrap();
+        assert!(load_smg_json(&with_sim).unwrap().similarity_matrix.is_none());
+
+        let _ = std::fs::remove_file(&plain);
+        let _ = std::fs::remove_file(&with_sim);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_timestamp_source_selects_commit_or_ingest_time() {
+        use crate::temporal::{re_rank_with_temporal, TemporalConfig, TimestampSource};
+
+        let now = 1_767_225_600u64;
+        let day = 86_400u64;
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        // Imported history: committed a year ago, ingested yesterday.
+        smg.ingest_turn(&ConversationTurn {
+            commit_id: Some("old".to_string()),
+            timestamp: now - 365 * day,
+            ingested_at: Some(now - day),
+            ..turn(1, "imported parser rewrite")
+        })
+        .unwrap();
+
+        // Ingest times survive a save/load roundtrip.
+        let path = scratch_path("ingested_at").with_extension("json");
+        save_smg_json(&smg, &path).unwrap();
+        let smg = load_smg_json(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(smg.notes[&0].source_ingested_at, vec![now - day]);
+
+        let candidates = smg
+            .retrieve_candidates("imported parser rewrite", 1, None, None, 0.0)
+            .unwrap();
+        let temporal_score = |source: TimestampSource| {
+            let cfg = TemporalConfig {
+                timestamp_source: source,
+                now_seconds: Some(now),
+                ..TemporalConfig::default()
+            };
+            re_rank_with_temporal(candidates.clone(), &cfg, None)[0].temporal_score
+        };
+
+        let commit = temporal_score(TimestampSource::Commit);
+        let ingest = temporal_score(TimestampSource::Ingest);
+        let blend = temporal_score(TimestampSource::Blend { ingest_weight: 0.5 });
+        assert!(commit < 0.01, "year-old commit should have decayed, got {}", commit);
+        assert!(ingest > 0.9, "day-old ingest should be fresh, got {}", ingest);
+        assert!((blend - (commit + ingest) / 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_save_smg_json_clusters_keeps_only_allowed_clusters() {
+        let mut smg = SpectralMemoryGraph::new().unwrap();
+        for i in 0..9u64 {
+            smg.ingest_turn(&turn(i, format!("clustered note {}", i))).unwrap();
+        }
+        smg.cluster_labels = Some(Array1::from(vec![0, 0, 0, 1, 1, 1, 2, 2, 2]));
+        smg.cluster_centroids = Some((0..3).map(|c| (c, vec![c as f32])).collect());
+        smg.cluster_centroid_norms = Some((0..3).map(|c| (c, c as f32)).collect());
+        smg.long_range_links = Some(vec![(0, 6, 0.9), (1, 4, 0.8), (2, 7, 0.7)]);
+        smg.notes.get_mut(&0).unwrap().related_note_links = vec![(6, 0.9), (3, 0.5)];
+
+        let path = scratch_path("clusters").with_extension("json");
+        save_smg_json_clusters(&smg, &path, &[0, 2]).unwrap();
+        let loaded = load_smg_json(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let mut ids: Vec<u32> = loaded.notes.keys().cloned().collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![0, 1, 2, 6, 7, 8]);
+        assert_eq!(loaded.cluster_labels.unwrap().to_vec(), vec![0, 0, 0, 2, 2, 2]);
+        let mut centroid_labels: Vec<usize> =
+            loaded.cluster_centroids.unwrap().keys().cloned().collect();
+        centroid_labels.sort_unstable();
+        assert_eq!(centroid_labels, vec![0, 2]);
+        assert_eq!(loaded.long_range_links.unwrap(), vec![(0, 6, 0.9), (2, 7, 0.7)]);
+        assert_eq!(loaded.notes[&0].related_note_links, vec![(6, 0.9)]);
+
+        smg.cluster_labels = None;
+        assert!(save_smg_json_clusters(&smg, &path, &[0]).is_err());
+    }
+}
@@ -18,6 +18,16 @@ pub struct ConversationTurn {
     pub symbol_id: Option<String>,
     pub ast_node_type: Option<String>,
     pub file_path: Option<String>,
+    /// Position (0-based) of this turn among the segments of its commit message.
+    #[serde(default)]
+    pub segment_index: Option<usize>,
+    /// Number of segments the commit message was split into.
+    #[serde(default)]
+    pub segment_count: Option<usize>,
+    /// When the turn entered the graph (unix epoch seconds). `None` is stamped with
+    /// the current time on ingest; `timestamp` stays the commit time.
+    #[serde(default)]
+    pub ingested_at: Option<u64>,
 }
 
 impl ConversationTurn {
@@ -28,4 +38,9 @@ impl ConversationTurn {
             .collect::<Vec<_>>()
             .join(" ")
     }
+
+    /// The ingest time to record for this turn: `ingested_at`, or now if unset.
+    pub fn ingested_at_or_now(&self) -> u64 {
+        self.ingested_at.unwrap_or_else(crate::temporal::unix_now)
+    }
 }
@@ -34,9 +34,44 @@ pub struct SMGNote {
     pub file_path: Option<String>,
     /// Structural link neighbors (note_ids).
     pub structural_links: Vec<u32>,
+    /// Position (0-based) of the commit segment this note was created from.
+    #[serde(default)]
+    pub segment_index: Option<usize>,
+    /// Number of segments the source commit was split into.
+    #[serde(default)]
+    pub segment_count: Option<usize>,
+    /// Parallel vector of ingest times (unix epoch seconds): when each source turn
+    /// entered the graph, as opposed to its commit time. Empty for notes loaded from
+    /// graphs written before ingest times were recorded.
+    #[serde(default)]
+    pub source_ingested_at: Vec<u64>,
 }
 
 impl SMGNote {
+    /// Create a note with no source turns, links or AST metadata; the norm is computed
+    /// from `embedding`.
+    pub fn new(note_id: u32, raw_content: impl Into<String>, embedding: Vec<f32>) -> Self {
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        Self {
+            note_id,
+            raw_content: raw_content.into(),
+            embedding,
+            norm,
+            source_turn_ids: Vec::new(),
+            source_commit_ids: Vec::new(),
+            source_timestamps: Vec::new(),
+            spectral_coords: None,
+            related_note_links: Vec::new(),
+            symbol_id: None,
+            ast_node_type: None,
+            file_path: None,
+            structural_links: Vec::new(),
+            segment_index: None,
+            segment_count: None,
+            source_ingested_at: Vec::new(),
+        }
+    }
+
     /// Update the note with a new turn, performing a weighted average of embeddings.
     pub fn update_with_turn(
         &mut self,
@@ -55,6 +90,15 @@ impl SMGNote {
         // Recompute norm after embedding update.
         self.norm = self.embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
 
+        self.record_source_turn(turn);
+    }
+
+    /// Record a turn as an additional source of this note without touching content or embedding.
+    ///
+    /// Appends the turn id, commit id, timestamp and ingest time to the parallel provenance vectors. This is
+    /// the merge step used for exact-duplicate turns, whose content and embedding already match
+    /// the note.
+    pub fn record_source_turn(&mut self, turn: &crate::model::conversation_turn::ConversationTurn) {
         // Record the source turn id.
         self.source_turn_ids.push(turn.turn_id);
 
@@ -65,6 +109,12 @@ impl SMGNote {
         // Record the timestamp (unix epoch seconds) in parallel with the turn id.
         self.source_timestamps.push(turn.timestamp);
 
+        // Record the ingest time only while the vector is aligned with the turn ids;
+        // legacy notes without ingest times stay empty rather than misaligned.
+        if self.source_ingested_at.len() + 1 == self.source_turn_ids.len() {
+            self.source_ingested_at.push(turn.ingested_at_or_now());
+        }
+
         // Set file_path if not already set (primary file for this note).
         if self.file_path.is_none() {
             self.file_path = turn.file_path.clone();
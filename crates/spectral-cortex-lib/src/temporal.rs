@@ -35,6 +35,27 @@ fn days_to_seconds(days: f32) -> u64 {
     ((days * 24.0 * 60.0 * 60.0).round() as u64).max(1)
 }
 
+/// Current time as unix epoch seconds (0 if the clock is before the epoch).
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Which candidate timestamp drives the temporal score.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampSource {
+    /// Commit (author) time of the source turn.
+    #[default]
+    Commit,
+    /// Time the turn was ingested into the graph.
+    Ingest,
+    /// Weighted blend of the commit and ingest scores; `ingest_weight` in [0.0, 1.0].
+    Blend { ingest_weight: f32 },
+}
+
 /// Temporal scoring modes.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -70,6 +91,10 @@ pub struct TemporalConfig {
     pub buckets: Option<Vec<(u64, f32)>>,
     /// Optional override of "now" for deterministic tests (unix epoch seconds).
     pub now_seconds: Option<u64>,
+    /// Timestamp driving decay. Default: Commit. Candidates without an ingest time
+    /// fall back to their commit time.
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
 }
 
 impl Default for TemporalConfig {
@@ -83,6 +108,7 @@ impl Default for TemporalConfig {
             boost_magnitude: None,
             buckets: None,
             now_seconds: None,
+            timestamp_source: TimestampSource::Commit,
         }
     }
 }
@@ -98,6 +124,8 @@ pub struct Candidate {
     pub raw_score: f32,
     /// Optional epoch seconds timestamp associated with the candidate.
     pub timestamp: Option<u64>,
+    /// Optional epoch seconds at which the candidate's turn was ingested.
+    pub ingested_at: Option<u64>,
 }
 
 /// Candidate with computed temporal and final scores produced by the re-ranker.
@@ -205,6 +233,23 @@ fn compute_temporal_score(
     }
 }
 
+/// Temporal score of `candidate` using the timestamp(s) selected by `cfg.timestamp_source`.
+fn candidate_temporal_score(candidate: &Candidate, now_seconds: u64, cfg: &TemporalConfig) -> f32 {
+    let commit_score = compute_temporal_score(candidate.timestamp, now_seconds, cfg);
+    let ingest_score = || match candidate.ingested_at {
+        Some(ts) => compute_temporal_score(Some(ts), now_seconds, cfg),
+        None => commit_score,
+    };
+    match cfg.timestamp_source {
+        TimestampSource::Commit => commit_score,
+        TimestampSource::Ingest => ingest_score(),
+        TimestampSource::Blend { ingest_weight } => {
+            let w = ingest_weight.clamp(0.0, 1.0);
+            (1.0 - w) * commit_score + w * ingest_score()
+        }
+    }
+}
+
 /// Re-rank a list of candidates using the provided `TemporalConfig`.
 ///
 /// - `candidates`: input candidate list (raw semantic scores must be in 0..1).
@@ -223,12 +268,7 @@ pub fn re_rank_with_temporal(
     use rayon::prelude::*;
 
     // Resolve now in epoch seconds. Priority: now_opt (argument) > cfg.now_seconds > SystemTime.
-    let now_seconds: u64 = now_opt.or(cfg.now_seconds).unwrap_or_else(|| {
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0)
-    });
+    let now_seconds: u64 = now_opt.or(cfg.now_seconds).unwrap_or_else(unix_now);
 
     // If temporal disabled, short-circuit to preserve raw_score semantics.
     if !cfg.enabled {
@@ -256,7 +296,7 @@ pub fn re_rank_with_temporal(
     let out: Vec<CandidateWithScores> = candidates
         .into_par_iter()
         .map(|c| {
-            let temporal_score = compute_temporal_score(c.timestamp, now_seconds, cfg);
+            let temporal_score = candidate_temporal_score(&c, now_seconds, cfg);
             let raw = c.raw_score.clamp(0.0, 1.0);
             // Weighted sum combination.
             let w = cfg.weight.clamp(0.0, 1.0);
@@ -302,6 +342,7 @@ mod tests {
             boost_magnitude: None,
             buckets: None,
             now_seconds: None,
+            timestamp_source: TimestampSource::Commit,
         };
         let now = 1_000_000u64;
         let candidate_time = now - 10;
@@ -322,6 +363,7 @@ mod tests {
             boost_magnitude: None,
             buckets: None,
             now_seconds: None,
+            timestamp_source: TimestampSource::Commit,
         };
         let now = 200u64;
         let candidate_time = 150u64; // age 50 -> normalized 1 - 50/100 = 0.5
@@ -340,6 +382,7 @@ mod tests {
             boost_magnitude: Some(0.8),
             buckets: None,
             now_seconds: None,
+            timestamp_source: TimestampSource::Commit,
         };
         let now = 10_000u64;
         let candidate_recent = now - 1800; // within window
@@ -361,6 +404,7 @@ mod tests {
             boost_magnitude: None,
             buckets: Some(vec![(86400, 1.0), (7 * 86400, 0.6), (30 * 86400, 0.3)]),
             now_seconds: None,
+            timestamp_source: TimestampSource::Commit,
         };
         let now = 10_000_000u64; // Use a larger now value to avoid overflow
         let c1 = now - 3600; // 1 hour -> bucket 86400 -> 1.0
@@ -398,18 +442,21 @@ mod tests {
             note_id: 1,
             raw_score: 0.90,
             timestamp: Some(a_ts),
+            ingested_at: None,
         };
         let b = Candidate {
             turn_id: 2,
             note_id: 2,
             raw_score: 0.85,
             timestamp: Some(b_ts),
+            ingested_at: None,
         };
         let c = Candidate {
             turn_id: 3,
             note_id: 3,
             raw_score: 0.80,
             timestamp: Some(c_ts),
+            ingested_at: None,
         };
 
         // Use a moderate weight so temporal effect can flip ordering for A vs B/C.
@@ -422,6 +469,7 @@ mod tests {
             boost_magnitude: None,
             buckets: None,
             now_seconds: None,
+            timestamp_source: TimestampSource::Commit,
         };
 
         let results = re_rank_with_temporal(vec![a.clone(), b.clone(), c.clone()], &cfg, Some(now));
@@ -453,12 +501,14 @@ mod tests {
             note_id: 1,
             raw_score: 0.70,
             timestamp: Some(now - 10),
+            ingested_at: None,
         };
         let b = Candidate {
             turn_id: 2,
             note_id: 2,
             raw_score: 0.90,
             timestamp: Some(now - 10000),
+            ingested_at: None,
         };
         // temporal disabled
         let cfg = TemporalConfig {
@@ -470,6 +520,7 @@ mod tests {
             boost_magnitude: None,
             buckets: None,
             now_seconds: None,
+            timestamp_source: TimestampSource::Commit,
         };
         let results = re_rank_with_temporal(vec![a.clone(), b.clone()], &cfg, Some(now));
         assert_eq!(results.len(), 2);
@@ -0,0 +1,41 @@
+//! The fake embedder's jitter is process-wide, so it lives in its own test binary
+//! instead of perturbing the library's unit tests that run in parallel.
+#![cfg(feature = "fake-embed")]
+
+use spectral_cortex::embed::{get_embedding, get_embeddings, set_fake_jitter};
+use spectral_cortex::graph::embedding_cache::query_embedding;
+
+#[test]
+fn test_fake_jitter_is_seeded_off_by_default_and_applies_on_all_threads() {
+    let exact = get_embedding("jitter probe").unwrap();
+    set_fake_jitter(7, 0.0);
+    assert_eq!(get_embedding("jitter probe").unwrap(), exact);
+    assert_eq!(query_embedding("jitter probe").unwrap(), exact);
+
+    set_fake_jitter(7, 0.01);
+    let jittered = get_embedding("jitter probe").unwrap();
+    assert_ne!(jittered, exact);
+    assert!(jittered
+        .iter()
+        .zip(exact.iter())
+        .all(|(a, b)| (a - b).abs() <= 0.01 + 1e-6));
+    // Same seed reproduces the noise, in single and batch calls alike.
+    assert_eq!(get_embedding("jitter probe").unwrap(), jittered);
+    let batch = get_embeddings(&["other".to_string(), "jitter probe".to_string()], None).unwrap();
+    assert_eq!(batch[1], jittered);
+    // Changing the jitter invalidates cached query embeddings.
+    assert_eq!(query_embedding("jitter probe").unwrap(), jittered);
+
+    // Worker threads see the same setting as the thread that set it.
+    let on_worker = std::thread::spawn(|| get_embedding("jitter probe").unwrap())
+        .join()
+        .unwrap();
+    assert_eq!(on_worker, jittered);
+
+    set_fake_jitter(8, 0.01);
+    assert_ne!(get_embedding("jitter probe").unwrap(), jittered);
+
+    set_fake_jitter(0, 0.0);
+    assert_eq!(get_embedding("jitter probe").unwrap(), exact);
+    assert_eq!(query_embedding("jitter probe").unwrap(), exact);
+}
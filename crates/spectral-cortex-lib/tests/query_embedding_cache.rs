@@ -0,0 +1,41 @@
+//! Counts fake embedder calls, which are tallied process-wide, so it lives in its own
+//! test binary where no other test embeds text concurrently.
+#![cfg(feature = "fake-embed")]
+
+use spectral_cortex::embed::{self, fake_embed_calls};
+use spectral_cortex::temporal::Candidate;
+use spectral_cortex::{ConversationTurn, SpectralMemoryGraph};
+
+#[test]
+fn test_repeated_query_is_embedded_once() {
+    let mut smg = SpectralMemoryGraph::new().unwrap();
+    smg.ingest_turn(&ConversationTurn {
+        turn_id: 1,
+        speaker: "tester".to_string(),
+        content: "tokenizer handles unicode".to_string(),
+        topic: "git".to_string(),
+        entities: Vec::new(),
+        commit_id: Some("c1".to_string()),
+        timestamp: 100,
+        symbol_id: None,
+        ast_node_type: None,
+        file_path: None,
+        segment_index: None,
+        segment_count: None,
+        ingested_at: None,
+    })
+    .unwrap();
+    let query = "query embedding cache probe";
+
+    let before = fake_embed_calls();
+    let first = smg.retrieve_candidates(query, 5, None, None, 0.0).unwrap();
+    let second = smg.retrieve_candidates(query, 5, None, None, 0.0).unwrap();
+    assert_eq!(fake_embed_calls() - before, 1);
+    let scores = |c: &[Candidate]| -> Vec<f32> { c.iter().map(|c| c.raw_score).collect() };
+    assert_eq!(scores(&first), scores(&second));
+
+    // Re-initialising the embedder invalidates cached query embeddings.
+    embed::init(1, 0).unwrap();
+    smg.retrieve_candidates(query, 5, None, None, 0.0).unwrap();
+    assert_eq!(fake_embed_calls() - before, 2);
+}
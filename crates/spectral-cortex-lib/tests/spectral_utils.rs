@@ -1,8 +1,10 @@
 use ndarray::array;
 use spectral_cortex::graph::spectral::{
-    assemble_embedding_matrix, compute_spectral_embeddings, cosine_similarity_matrix,
-    degree_vector, normalized_laplacian, sparsify_adj, spectral_decomposition,
+    assemble_embedding_matrix, assemble_embedding_matrix_with_budget, compute_spectral_embeddings,
+    cosine_similarity_matrix, degree_vector, normalized_laplacian, run_kmeans_on_spectral,
+    run_kmeans_on_spectral_with_params, sparsify_adj, spectral_decomposition,
 };
+use spectral_cortex::SpectralBuildConfig;
 use spectral_cortex::model::smg_note::SMGNote;
 use std::collections::HashMap;
 
@@ -13,37 +15,15 @@ fn test_assemble_and_cosine() {
     notes.insert(
         1,
         SMGNote {
-            note_id: 1,
-            raw_content: "a".to_string(),
-            embedding: vec![1.0_f32, 0.0, 0.0],
-            norm: 1.0,
-            source_turn_ids: vec![],
-            source_commit_ids: vec![],
-            source_timestamps: vec![],
-            spectral_coords: None,
-            related_note_links: vec![],
-            symbol_id: None,
-            ast_node_type: None,
-            structural_links: vec![],
-            file_path: Some("file1.rs".to_string())
+            file_path: Some("file1.rs".to_string()),
+            ..SMGNote::new(1, "a", vec![1.0_f32, 0.0, 0.0])
         },
     );
     notes.insert(
         2,
         SMGNote {
-            note_id: 2,
-            raw_content: "b".to_string(),
-            embedding: vec![0.0_f32, 1.0, 0.0],
-            norm: 1.0,
-            source_turn_ids: vec![],
-            source_commit_ids: vec![],
-            source_timestamps: vec![],
-            spectral_coords: None,
-            related_note_links: vec![],
-            symbol_id: None,
-            ast_node_type: None,
-            structural_links: vec![],
-            file_path: Some("file1.rs".to_string())
+            file_path: Some("file1.rs".to_string()),
+            ..SMGNote::new(2, "b", vec![0.0_f32, 1.0, 0.0])
         },
     );
 
@@ -60,6 +40,26 @@ fn test_assemble_and_cosine() {
     assert!(sim[(0, 1)].abs() < 1e-6);
 }
 
+#[test]
+fn test_assemble_with_budget_rejects_oversized_matrix() {
+    let mut notes: HashMap<u32, SMGNote> = HashMap::new();
+    for nid in 0..4u32 {
+        notes.insert(nid, SMGNote::new(nid, format!("n{}", nid), vec![0.5_f32; 8]));
+    }
+    let order = vec![0u32, 1, 2, 3];
+
+    // 4 x 8 f32 = 128 bytes.
+    let mat = assemble_embedding_matrix_with_budget(&notes, &order, Some(128)).unwrap();
+    assert_eq!(mat.dim(), (4, 8));
+    assert!(assemble_embedding_matrix_with_budget(&notes, &order, None).is_ok());
+
+    let err = assemble_embedding_matrix_with_budget(&notes, &order, Some(64)).unwrap_err();
+    let msg = err.to_string();
+    assert!(msg.contains("needs 128 bytes"), "{}", msg);
+    assert!(msg.contains("budget of 64 bytes"), "{}", msg);
+    assert!(msg.contains("shards"), "{}", msg);
+}
+
 #[test]
 fn test_sparsify_and_degree() {
     // Create a small similarity matrix
@@ -117,3 +117,47 @@ fn test_spectral_decomposition_and_eigengap() {
         assert!(v.is_finite());
     }
 }
+
+#[test]
+fn test_kmeans_stop_conditions() {
+    // Two well-separated groups of four points each.
+    let spec = array![
+        [1.0_f32, 0.0],
+        [0.95, 0.05],
+        [0.9, 0.1],
+        [0.97, -0.02],
+        [0.0, 1.0],
+        [0.05, 0.95],
+        [0.1, 0.9],
+        [-0.02, 0.97],
+    ];
+    let same_partition = |labels: &ndarray::Array1<usize>| {
+        labels.len() == 8
+            && labels.iter().all(|l| *l < 2)
+            && labels.iter().take(4).all(|l| *l == labels[0])
+            && labels.iter().skip(4).all(|l| *l == labels[4])
+            && labels[0] != labels[4]
+    };
+
+    // A single iteration still yields one valid label per row.
+    let one_iter = run_kmeans_on_spectral_with_params(&spec, 2, 1, 1e-4).unwrap();
+    assert_eq!(one_iter.len(), 8);
+    assert!(one_iter.iter().all(|l| *l < 2));
+
+    // Tight and loose tolerances converge to the same, stable partition.
+    let default = run_kmeans_on_spectral(&spec, 2).unwrap();
+    let tight = run_kmeans_on_spectral_with_params(&spec, 2, 500, 1e-9).unwrap();
+    assert!(same_partition(&default), "{:?}", default);
+    assert!(same_partition(&tight), "{:?}", tight);
+
+    let invalid = SpectralBuildConfig {
+        kmeans_max_iters: 0,
+        ..SpectralBuildConfig::default()
+    };
+    assert!(invalid.validate().is_err());
+    let invalid = SpectralBuildConfig {
+        kmeans_tolerance: 0.0,
+        ..SpectralBuildConfig::default()
+    };
+    assert!(invalid.validate().is_err());
+}